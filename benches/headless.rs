@@ -0,0 +1,25 @@
+//! Compares emulating frames with and without rendering. Run with `cargo bench --bench headless`.
+
+use std::time::Instant;
+use rs_desmume::{DeSmuME, SpeedMode};
+
+const FRAMES: u32 = 600;
+
+fn main() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+
+    let start = Instant::now();
+    emu.run_frames(FRAMES);
+    let rendered = start.elapsed();
+
+    emu.set_headless(true);
+    let start = Instant::now();
+    emu.run_frames(FRAMES);
+    let headless = start.elapsed();
+
+    println!(
+        "{} frames: {:?} rendered, {:?} headless ({:.2}x)",
+        FRAMES, rendered, headless, rendered.as_secs_f64() / headless.as_secs_f64().max(f64::EPSILON)
+    );
+}
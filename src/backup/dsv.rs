@@ -0,0 +1,174 @@
+//! Conversion between DeSmuME's `.dsv` backup files and raw `.sav` dumps.
+//!
+//! A `.dsv` file is the raw backup memory (padded to a valid size), followed by a footer that
+//! describes the backup memory and ends with the `|-DESMUME SAVE-|` cookie. Raw `.sav` files,
+//! as used by flashcarts and most other emulators, only contain the backup memory.
+
+use crate::backup::SaveType;
+use crate::DeSmuMEError;
+
+const FOOTER_TEXT: &[u8] = b"|<--Snip above here to create a raw sav by excluding this DeSmuME savedata footer:";
+const COOKIE: &[u8] = b"|-DESMUME SAVE-|";
+/// Size of the footer information block between the text and the cookie.
+const INFO_SIZE: usize = 24;
+const FOOTER_VERSION: u32 = 0;
+
+/// The information in the footer of a `.dsv` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DsvInfo {
+    /// Number of bytes the game actually wrote.
+    pub actual_size: u32,
+    /// Number of bytes of backup memory stored in the file.
+    pub padded_size: u32,
+    pub save_type: SaveType,
+    /// Number of address bytes the game uses to access the backup memory.
+    pub addr_size: u32,
+    /// Size of the backup memory chip.
+    pub mem_size: u32,
+}
+
+impl DsvInfo {
+    /// Check that the sizes are consistent with each other and with the save type.
+    fn validate(&self) -> Result<(), DeSmuMEError> {
+        if self.save_type == SaveType::Auto {
+            return Err(invalid("The save type of a backup file must not be Auto."));
+        }
+        if self.actual_size > self.padded_size {
+            return Err(invalid("The written size is larger than the stored size."));
+        }
+        if Some(self.mem_size as usize) != self.save_type.size() {
+            return Err(invalid(&format!("The memory size {} does not match the save type {:?}.", self.mem_size, self.save_type)));
+        }
+        Ok(())
+    }
+}
+
+/// A DeSmuME `.dsv` backup file.
+#[derive(Debug, Clone)]
+pub struct DsvFile {
+    pub info: DsvInfo,
+    /// The raw backup memory.
+    pub data: Vec<u8>,
+}
+
+impl DsvFile {
+    /// Returns true, if `data` ends with a DeSmuME backup footer.
+    pub fn is_dsv(data: &[u8]) -> bool {
+        data.ends_with(COOKIE)
+    }
+
+    /// Parse a `.dsv` file.
+    pub fn parse(data: &[u8]) -> Result<Self, DeSmuMEError> {
+        if !Self::is_dsv(data) || data.len() < COOKIE.len() + INFO_SIZE {
+            return Err(invalid("The file does not have a DeSmuME backup footer."));
+        }
+        let info_start = data.len() - COOKIE.len() - INFO_SIZE;
+        let field = |i: usize| u32::from_le_bytes(data[info_start + i * 4..info_start + i * 4 + 4].try_into().unwrap());
+        let version = field(5);
+        if version != FOOTER_VERSION {
+            return Err(invalid(&format!("Unsupported footer version {}.", version)));
+        }
+        let save_type = save_type_from_dsv(field(2))
+            .ok_or_else(|| invalid(&format!("Unknown save type {}.", field(2))))?;
+        let info = DsvInfo {
+            actual_size: field(0),
+            padded_size: field(1),
+            save_type,
+            addr_size: field(3),
+            mem_size: field(4),
+        };
+
+        info.validate()?;
+        // The text before the footer information is only meant for humans and may be missing.
+        let data_end = if data[..info_start].ends_with(FOOTER_TEXT) { info_start - FOOTER_TEXT.len() } else { info_start };
+        if (info.padded_size as usize) > data_end {
+            return Err(invalid("The backup memory is truncated."));
+        }
+        Ok(Self { info, data: data[..info.padded_size as usize].to_vec() })
+    }
+
+    /// Create a `.dsv` file from a raw `.sav` dump.
+    ///
+    /// If `save_type` is [`SaveType::Auto`], the save type is detected from the size of the dump.
+    /// Dumps smaller than the backup memory are padded with `0xFF`, as DeSmuME does.
+    pub fn from_raw(raw: &[u8], save_type: SaveType) -> Result<Self, DeSmuMEError> {
+        let save_type = match save_type {
+            SaveType::Auto => SaveType::from_size(raw.len())
+                .ok_or_else(|| invalid(&format!("Could not detect the save type of a {} byte save.", raw.len())))?,
+            save_type => save_type,
+        };
+        let mem_size = save_type.size().unwrap();
+        if raw.len() > mem_size {
+            return Err(invalid(&format!("The save is larger than the backup memory of {:?}.", save_type)));
+        }
+        let mut data = raw.to_vec();
+        data.resize(mem_size, 0xFF);
+        Ok(Self {
+            info: DsvInfo {
+                actual_size: raw.len() as u32,
+                padded_size: mem_size as u32,
+                save_type,
+                addr_size: addr_size(save_type),
+                mem_size: mem_size as u32,
+            },
+            data,
+        })
+    }
+
+    /// Serialize to the `.dsv` format.
+    ///
+    /// Fails if the information does not describe the data, eg. if the save type is
+    /// [`SaveType::Auto`] or the padded size is not the length of the data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DeSmuMEError> {
+        self.info.validate()?;
+        if self.info.padded_size as usize != self.data.len() {
+            return Err(invalid("The stored size does not match the length of the data."));
+        }
+        let mut out = self.data.clone();
+        out.extend(FOOTER_TEXT);
+        for value in [
+            self.info.actual_size, self.info.padded_size, self.info.save_type as u32 - 1,
+            self.info.addr_size, self.info.mem_size, FOOTER_VERSION
+        ] {
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(COOKIE);
+        Ok(out)
+    }
+
+    /// The contents as a raw `.sav` dump, padded to the size of the backup memory.
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut raw = self.data.clone();
+        raw.resize(raw.len().max(self.info.mem_size as usize), 0xFF);
+        raw
+    }
+}
+
+/// Convert a DeSmuME `.dsv` file into a raw `.sav` dump.
+pub fn dsv_to_sav(dsv: &[u8]) -> Result<Vec<u8>, DeSmuMEError> {
+    Ok(DsvFile::parse(dsv)?.to_raw())
+}
+
+/// Convert a raw `.sav` dump into a DeSmuME `.dsv` file. See [`DsvFile::from_raw`].
+pub fn sav_to_dsv(sav: &[u8], save_type: SaveType) -> Result<Vec<u8>, DeSmuMEError> {
+    DsvFile::from_raw(sav, save_type)?.to_bytes()
+}
+
+/// The footer stores the save type without the auto-detect entry.
+fn save_type_from_dsv(value: u32) -> Option<SaveType> {
+    SaveType::ALL.into_iter().find(|t| *t as u32 == value + 1)
+}
+
+fn addr_size(save_type: SaveType) -> u32 {
+    match save_type {
+        SaveType::Auto => 0,
+        SaveType::Eeprom4k => 1,
+        SaveType::Eeprom64k | SaveType::Eeprom512k | SaveType::Fram256k => 2,
+        save_type if save_type.size().unwrap() <= 1 << 24 => 3,
+        _ => 4,
+    }
+}
+
+fn invalid(reason: &str) -> DeSmuMEError {
+    DeSmuMEError::InvalidBackup(reason.to_owned())
+}
@@ -0,0 +1,121 @@
+pub mod dsv;
+
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+use crate::DeSmuMEError;
+use crate::backup::dsv::DsvFile;
+use crate::savestate::{self, SavestateFile};
+
+/// Cartridge backup memory (SRAM) types, as supported by DeSmuME.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveType {
+    /// Detect the save type from the way the game accesses the backup memory.
+    Auto = 0,
+    Eeprom4k = 1,
+    Eeprom64k = 2,
+    Eeprom512k = 3,
+    Fram256k = 4,
+    Flash2m = 5,
+    Flash4m = 6,
+    Flash8m = 7,
+    Flash16m = 8,
+    Flash32m = 9,
+    Flash64m = 10,
+    Flash128m = 11,
+    Flash256m = 12,
+    Flash512m = 13
+}
+
+impl SaveType {
+    pub(crate) const ALL: [SaveType; 13] = [
+        SaveType::Eeprom4k, SaveType::Eeprom64k, SaveType::Eeprom512k, SaveType::Fram256k,
+        SaveType::Flash2m, SaveType::Flash4m, SaveType::Flash8m, SaveType::Flash16m,
+        SaveType::Flash32m, SaveType::Flash64m, SaveType::Flash128m, SaveType::Flash256m,
+        SaveType::Flash512m
+    ];
+
+    /// The size of the backup memory in bytes, `None` for [`SaveType::Auto`].
+    pub fn size(&self) -> Option<usize> {
+        const KBIT: usize = 1024 / 8;
+        const MBIT: usize = 1024 * KBIT;
+        Some(match self {
+            SaveType::Auto => return None,
+            SaveType::Eeprom4k => 4 * KBIT,
+            SaveType::Eeprom64k => 64 * KBIT,
+            SaveType::Eeprom512k => 512 * KBIT,
+            SaveType::Fram256k => 256 * KBIT,
+            SaveType::Flash2m => 2 * MBIT,
+            SaveType::Flash4m => 4 * MBIT,
+            SaveType::Flash8m => 8 * MBIT,
+            SaveType::Flash16m => 16 * MBIT,
+            SaveType::Flash32m => 32 * MBIT,
+            SaveType::Flash64m => 64 * MBIT,
+            SaveType::Flash128m => 128 * MBIT,
+            SaveType::Flash256m => 256 * MBIT,
+            SaveType::Flash512m => 512 * MBIT
+        })
+    }
+
+    /// Returns the save type with a backup memory of exactly `size` bytes.
+    pub fn from_size(size: usize) -> Option<SaveType> {
+        Self::ALL.into_iter().find(|t| t.size() == Some(size))
+    }
+}
+
+/// Access the cartridge backup memory (SRAM) of the loaded game.
+///
+/// DeSmuME does not expose the backup memory directly, it is read from and written to through
+/// in-memory savestates, which contain it. Writing it therefore also restores the rest of the
+/// emulator state to what it was when the write started, so no frames should be emulated in
+/// between. DeSmuME keeps its own copy of the backup memory in a `.dsv` file next to the ROM.
+pub struct DeSmuMEBackup(pub(crate) PhantomData<()>);
+
+impl DeSmuMEBackup {
+    /// Returns the current contents of the backup memory. This is empty if the save type is
+    /// auto-detected and the game did not access the backup memory yet.
+    pub fn read(&self) -> Result<Vec<u8>, DeSmuMEError> {
+        let state = SavestateFile::parse(&savestate::save_to_vec()?)?;
+        state.backup_data()
+            .map(|data| data.to_vec())
+            .ok_or_else(|| DeSmuMEError::BackupFailed("The emulator state does not contain the backup memory.".to_owned()))
+    }
+
+    /// Replace the contents of the backup memory. `data` must fit into the backup memory, see
+    /// [`SavestateFile::set_backup_data`].
+    ///
+    /// DeSmuME has no function to write the backup memory, so this is a full savestate round
+    /// trip: the emulator state is saved, the backup memory is replaced in it and the state is
+    /// loaded again.
+    ///
+    /// Games usually only read their save data while booting, so this should be done right after
+    /// opening the ROM (see [`crate::DeSmuME::open_with_backup`]) or followed by a reset.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), DeSmuMEError> {
+        let mut state = SavestateFile::parse(&savestate::save_to_vec()?)?;
+        state.set_backup_data(data)?;
+        savestate::load_from_slice(&state.to_bytes())
+    }
+
+    /// Replace the contents of the backup memory with a save file. Both raw save files (`.sav`)
+    /// and DeSmuME's own `.dsv` files are supported. See [`DeSmuMEBackup::write`].
+    pub fn import_file(&mut self, path: &Path) -> Result<(), DeSmuMEError> {
+        let data = fs::read(path)?;
+        if DsvFile::is_dsv(&data) {
+            self.write(&DsvFile::parse(&data)?.data)
+        } else {
+            self.write(&data)
+        }
+    }
+
+    /// Write the contents of the backup memory to a raw save file (`.sav`). Fails if the backup
+    /// memory is empty, see [`DeSmuMEBackup::read`].
+    pub fn export_file(&self, path: &Path) -> Result<(), DeSmuMEError> {
+        let data = self.read()?;
+        if data.is_empty() {
+            return Err(DeSmuMEError::BackupFailed("The game did not access the backup memory yet.".to_owned()));
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+//! Arranging both screens in one image, see [`Compositor`].
+
+use crate::framebuffer::{Frame, Screen};
+use crate::screenshot::ScreenSelection;
+use crate::DeSmuMEError;
+
+/// One of the two screens of the DS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DsScreen {
+    Top,
+    Bottom
+}
+
+/// How the screens are arranged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// The top screen above the bottom screen.
+    Vertical,
+    /// The top screen left of the bottom screen.
+    Horizontal,
+    /// The `large` screen at twice the size, with the other screen to the right of it,
+    /// aligned to the bottom.
+    Hybrid { large: DsScreen },
+    /// Only one screen.
+    Single(DsScreen)
+}
+
+/// Clockwise rotation of the composed image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Deg90,
+    Deg180,
+    Deg270
+}
+
+/// Composes both screens into one image. The screens are arranged according to the layout,
+/// separated by the gap, then the image is rotated and finally scaled.
+///
+/// Only screenshots and video recording use the compositor. [`crate::DeSmuMESdlWindow`] is drawn
+/// by DeSmuME itself in the native layout; to display a composed image, draw the result of
+/// [`Compositor::compose`] in your own window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compositor {
+    pub layout: Layout,
+    pub rotation: Rotation,
+    /// Pixels between the screens. Not used by [`Layout::Single`].
+    pub gap: usize,
+    /// RGBA color of the gap and of areas not covered by a screen.
+    pub gap_color: [u8; 4],
+    /// Integer scale factor, applied with nearest neighbour scaling. Must be at least 1.
+    pub scale: usize
+}
+
+impl Default for Compositor {
+    /// Both screens below each other, as on the DS.
+    fn default() -> Self {
+        Self { layout: Layout::Vertical, rotation: Rotation::None, gap: 0, gap_color: [0, 0, 0, 0xFF], scale: 1 }
+    }
+}
+
+impl From<ScreenSelection> for Compositor {
+    fn from(screens: ScreenSelection) -> Self {
+        let layout = match screens {
+            ScreenSelection::Top => Layout::Single(DsScreen::Top),
+            ScreenSelection::Bottom => Layout::Single(DsScreen::Bottom),
+            ScreenSelection::Both => Layout::Vertical
+        };
+        Self { layout, ..Default::default() }
+    }
+}
+
+impl Compositor {
+    /// Compose the screens of `frame`.
+    pub fn compose(&self, frame: &Frame) -> Result<RgbaImage, DeSmuMEError> {
+        self.compose_screens(&frame.top().into(), &frame.bottom().into())
+    }
+
+    /// Compose both screens from RGBX color values, as returned by
+    /// [`crate::LoadedDeSmuME::display_buffer_as_rgbx`] or [`crate::FrameUpdate::rgbx`].
+    /// `width` and `height` are the size of a single screen.
+    pub fn compose_rgbx(&self, rgbx: &[u8], width: usize, height: usize) -> Result<RgbaImage, DeSmuMEError> {
+        let len = width * height * 4;
+        if rgbx.len() < len * 2 {
+            return Err(DeSmuMEError::BufferTooSmall { required: len * 2, actual: rgbx.len() });
+        }
+        let screen = |data: &[u8]| {
+            let mut data = data.to_vec();
+            data.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 0xFF);
+            RgbaImage { width, height, data }
+        };
+        self.compose_screens(&screen(&rgbx[..len]), &screen(&rgbx[len..len * 2]))
+    }
+
+    /// Returns the size of the composed image for screens of the given size.
+    pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+        let (width, height) = match self.layout {
+            Layout::Vertical => (width, height * 2 + self.gap),
+            Layout::Horizontal => (width * 2 + self.gap, height),
+            Layout::Hybrid { .. } => (width * 3 + self.gap, height * 2),
+            Layout::Single(_) => (width, height)
+        };
+        let (width, height) = match self.rotation {
+            Rotation::None | Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width)
+        };
+        (width * self.scale, height * self.scale)
+    }
+
+    fn compose_screens(&self, top: &RgbaImage, bottom: &RgbaImage) -> Result<RgbaImage, DeSmuMEError> {
+        if self.scale == 0 {
+            return Err(DeSmuMEError::InvalidCompositorScale);
+        }
+        let (width, height) = (top.width, top.height);
+        let screen = |screen| match screen {
+            DsScreen::Top => top,
+            DsScreen::Bottom => bottom
+        };
+        let image = match self.layout {
+            Layout::Vertical => {
+                let mut image = RgbaImage::filled(width, height * 2 + self.gap, self.gap_color);
+                image.draw(top, 0, 0, 1);
+                image.draw(bottom, 0, height + self.gap, 1);
+                image
+            }
+            Layout::Horizontal => {
+                let mut image = RgbaImage::filled(width * 2 + self.gap, height, self.gap_color);
+                image.draw(top, 0, 0, 1);
+                image.draw(bottom, width + self.gap, 0, 1);
+                image
+            }
+            Layout::Hybrid { large } => {
+                let small = match large {
+                    DsScreen::Top => DsScreen::Bottom,
+                    DsScreen::Bottom => DsScreen::Top
+                };
+                let mut image = RgbaImage::filled(width * 3 + self.gap, height * 2, self.gap_color);
+                image.draw(screen(large), 0, 0, 2);
+                image.draw(screen(small), width * 2 + self.gap, height, 1);
+                image
+            }
+            Layout::Single(single) => screen(single).clone()
+        };
+        Ok(image.rotated(self.rotation).scaled(self.scale))
+    }
+}
+
+/// An image of RGBA color values, line by line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>
+}
+
+impl RgbaImage {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the RGBA color values.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the color of the pixel at the given position, or `None` if it is outside of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 4]> {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 4;
+            Some(self.data[offset..offset + 4].try_into().unwrap())
+        } else {
+            None
+        }
+    }
+
+    fn filled(width: usize, height: usize, color: [u8; 4]) -> Self {
+        Self { width, height, data: color.repeat(width * height) }
+    }
+
+    /// Draw `image` scaled by `scale` with its top left corner at `x`, `y`.
+    fn draw(&mut self, image: &RgbaImage, x: usize, y: usize, scale: usize) {
+        for dy in 0..image.height * scale {
+            for dx in 0..image.width * scale {
+                let from = ((dy / scale) * image.width + dx / scale) * 4;
+                let to = ((y + dy) * self.width + x + dx) * 4;
+                self.data[to..to + 4].copy_from_slice(&image.data[from..from + 4]);
+            }
+        }
+    }
+
+    fn rotated(self, rotation: Rotation) -> Self {
+        let (width, height) = (self.width, self.height);
+        let source = |x: usize, y: usize| match rotation {
+            Rotation::None => (x, y),
+            Rotation::Deg90 => (y, height - 1 - x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (width - 1 - y, x)
+        };
+        let (new_width, new_height) = match rotation {
+            Rotation::None => return self,
+            Rotation::Deg180 => (width, height),
+            Rotation::Deg90 | Rotation::Deg270 => (height, width)
+        };
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..new_height {
+            for x in 0..new_width {
+                let (sx, sy) = source(x, y);
+                let offset = (sy * width + sx) * 4;
+                data.extend_from_slice(&self.data[offset..offset + 4]);
+            }
+        }
+        Self { width: new_width, height: new_height, data }
+    }
+
+    fn scaled(self, scale: usize) -> Self {
+        if scale == 1 {
+            return self;
+        }
+        let mut image = Self::filled(self.width * scale, self.height * scale, [0; 4]);
+        image.draw(&self, 0, 0, scale);
+        image
+    }
+}
+
+impl From<Screen<'_>> for RgbaImage {
+    fn from(screen: Screen<'_>) -> Self {
+        Self { width: screen.width(), height: screen.height(), data: screen.to_rgba8() }
+    }
+}
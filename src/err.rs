@@ -1,22 +1,86 @@
+use std::error::Error as StdError;
 use std::ffi::NulError;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
+use crate::DeSmuME;
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum DeSmuMEError {
     #[error("The emulator is already instantiated.")]
     AlreadyInit,
+    #[error("The emulator was freed and can not be initialized again.")]
+    Freed,
     #[error("Failed to initialize the emulator.")]
     FailedInit,
     #[error("Failed to open the ROM file.")]
     FailedOpen,
+    #[error("The ROM image is too small to contain a header ({0} bytes).")]
+    RomTooSmall(usize),
+    #[error("The ROM header checksum is invalid (expected {expected:#06x}, calculated {actual:#06x}).")]
+    RomBadHeaderChecksum { expected: u16, actual: u16 },
+    #[error("The ROM image is truncated (expected at least {expected} bytes, got {actual}).")]
+    RomTruncated { expected: usize, actual: usize },
+    #[error("The path '{0}' can not be passed to DeSmuME.")]
+    InvalidPath(PathBuf),
     #[error("Failed to initialize the SDL window.")]
     FailedInitWindow,
-    #[error("Failed to load savestate.")]
-    LoadSavestateFailed,
-    #[error("Failed to save savestate.")]
-    SaveSavestateFailed,
+    #[error("Failed to load savestate: {0}")]
+    LoadSavestateFailed(String),
+    #[error("Failed to save savestate: {0}")]
+    SaveSavestateFailed(String),
+    #[error("Invalid savestate: {0}")]
+    InvalidSavestate(String),
+    #[error("The address {0:#010x} is not available.")]
+    InvalidAddress(u32),
+    #[error("A memory request of {len} bytes exceeds the maximum of {max} bytes.")]
+    MemoryRequestTooLarge { len: usize, max: u32 },
+    #[error("Invalid savestate slot {0}.")]
+    InvalidSavestateSlot(u8),
+    #[error("The savestate slot {0} is empty.")]
+    SavestateSlotEmpty(u8),
+    #[error("The file of savestate slot {0} could not be located.")]
+    SavestateSlotPathUnknown(u8),
+    #[error("Invalid savestate metadata: {0}")]
+    InvalidSavestateMetadata(String),
+    #[error("Could not parse the savestate date '{0}'.")]
+    InvalidSavestateDate(String),
+    #[error("Failed to access the backup memory: {0}")]
+    BackupFailed(String),
+    #[error("Invalid backup file: {0}")]
+    InvalidBackup(String),
+    #[error("The condition was not met within {0} frames.")]
+    Timeout(u32),
+    #[error("Invalid speed {0}, must be between 0.25 and 8.")]
+    InvalidSpeed(f32),
+    #[error("The buffer holds {actual} elements, but {required} are required.")]
+    BufferTooSmall { required: usize, actual: usize },
+    #[error("The image format of '{0}' is not supported.")]
+    UnsupportedImageFormat(PathBuf),
+    #[error("The scale of the compositor must be at least 1.")]
+    InvalidCompositorScale,
+    #[error("The video format of '{0}' is not supported.")]
+    UnsupportedVideoFormat(PathBuf),
+    #[error("The video exceeds the maximum file size of its format.")]
+    VideoTooLarge,
+    #[error("No video is being recorded.")]
+    NotRecording,
+    #[error("Rewinding is not enabled.")]
+    RewindNotEnabled,
+    #[error("No snapshot to rewind to was taken yet.")]
+    RewindEmpty,
+    #[error("The emulator thread has stopped.")]
+    EmulatorThreadStopped,
+    #[error("No ROM is loaded.")]
+    NoRomLoaded,
+    #[error("The emulator was lost, because opening a ROM panicked.")]
+    EmulatorLost,
+    #[error("Worker process error: {0}")]
+    Remote(String),
+    #[error("The connection to the worker process was lost.")]
+    RemoteDisconnected,
     #[error("{0}")]
     MoviePlayError(String),
     #[error("No movie is active.")]
@@ -26,7 +90,9 @@ pub enum DeSmuMEError {
     #[error("Failed to initialize the joystick controls.")]
     FailedInitJoystick,
     #[error("Null error while trying to convert string.")]
-    NulError(#[source] NulError)
+    NulError(#[source] NulError),
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error)
 }
 
 impl From<NulError> for DeSmuMEError {
@@ -34,3 +100,56 @@ impl From<NulError> for DeSmuMEError {
         Self::NulError(e)
     }
 }
+
+impl From<io::Error> for DeSmuMEError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Error returned when opening a ROM with [`DeSmuME::open`] (or one of the other `open`
+/// functions) fails. It contains the emulator, so that another ROM can be opened.
+pub struct OpenError {
+    pub(crate) emu: DeSmuME,
+    pub(crate) error: DeSmuMEError
+}
+
+impl OpenError {
+    /// The reason opening the ROM failed.
+    pub fn error(&self) -> &DeSmuMEError {
+        &self.error
+    }
+
+    /// Get the emulator back, eg. to open another ROM.
+    pub fn into_emulator(self) -> DeSmuME {
+        self.emu
+    }
+
+    pub fn into_parts(self) -> (DeSmuME, DeSmuMEError) {
+        (self.emu, self.error)
+    }
+}
+
+impl Debug for OpenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenError").field("error", &self.error).finish_non_exhaustive()
+    }
+}
+
+impl Display for OpenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+
+impl StdError for OpenError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.error.source()
+    }
+}
+
+impl From<OpenError> for DeSmuMEError {
+    fn from(e: OpenError) -> Self {
+        e.error
+    }
+}
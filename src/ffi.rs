@@ -11,13 +11,9 @@ pub enum StartFrom {
 
 pub const GPU_FRAMEBUFFER_NATIVE_WIDTH: usize = 256;
 pub const GPU_FRAMEBUFFER_NATIVE_HEIGHT: usize = 192;
-// The framebuffer *may* be bigger (more pages etc.) but this is the only relevant parts of it.
-// Also yes this constant is defined is a bit weird, but I just took it from the DeSmuME source
-// (see _displayInfo.framebufferPageSize = ... in GPU.cpp).
-pub const FRAMEBUFFER_SIZE: usize = ((GPU_FRAMEBUFFER_NATIVE_WIDTH * GPU_FRAMEBUFFER_NATIVE_HEIGHT) + (GPU_FRAMEBUFFER_NATIVE_WIDTH * GPU_FRAMEBUFFER_NATIVE_HEIGHT)) * 2;
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct SimpleDate {
     pub year: c_int,
     pub month: c_int,
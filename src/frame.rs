@@ -0,0 +1,89 @@
+//! Frame and lag frame counters.
+//!
+//! The counters belong to a [`crate::LoadedDeSmuME`] and are shared with its
+//! [`crate::DeSmuMEMovie`] and [`crate::DeSmuMESavestate`], which keep them in sync with loaded
+//! savestates.
+
+use std::cell::Cell;
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::ffi::*;
+
+/// Address of the KEYINPUT register. Games read it to poll the keypad.
+pub(crate) const KEYINPUT: u32 = 0x04000130;
+
+/// Whether the keypad was read during the current frame. Memory callbacks of DeSmuME get no
+/// user data, so this can not be stored with the counters. There is only one emulator per
+/// process, see [`crate::DeSmuME::init`].
+static POLLED: AtomicBool = AtomicBool::new(false);
+/// The read callback for KEYINPUT registered by the user.
+static KEYPAD_READ_CALLBACK: Mutex<MemoryCbFnc> = Mutex::new(None);
+
+/// The frame and lag frame counts of the emulator, see [`crate::LoadedDeSmuME::frame_counters`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameCounters {
+    /// Frames emulated since the ROM was opened or the emulator was reset.
+    pub frames: u64,
+    /// Frames during which the game did not read the keypad.
+    pub lag_frames: u64,
+}
+
+/// The counters of an emulator, shared with its movie and savestate objects.
+#[derive(Debug, Default)]
+pub(crate) struct FrameCounter {
+    counters: Cell<FrameCounters>,
+    /// Whether the last emulated frame was a lag frame.
+    lagged: Cell<bool>,
+}
+
+impl FrameCounter {
+    pub(crate) fn get(&self) -> FrameCounters {
+        self.counters.get()
+    }
+
+    pub(crate) fn is_lag_frame(&self) -> bool {
+        self.lagged.get()
+    }
+
+    /// Set the counters, eg. when a savestate is loaded.
+    pub(crate) fn set(&self, counters: FrameCounters) {
+        self.counters.set(counters);
+        self.lagged.set(false);
+    }
+
+    /// Reset the counters, after a ROM was opened or the emulator was reset.
+    /// This also (re-)installs the memory callback used for lag frame detection.
+    pub(crate) fn reset(&self) {
+        self.set(FrameCounters::default());
+        unsafe { desmume_memory_register_read(KEYINPUT as c_int, 2, Some(keypad_read)) }
+    }
+
+    pub(crate) fn begin_frame(&self) {
+        POLLED.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn end_frame(&self) {
+        let lagged = !POLLED.load(Ordering::Relaxed);
+        let counters = self.counters.get();
+        self.counters.set(FrameCounters {
+            frames: counters.frames + 1,
+            lag_frames: counters.lag_frames + lagged as u64,
+        });
+        self.lagged.set(lagged);
+    }
+}
+
+/// Set the callback run when the game reads the KEYINPUT register, see
+/// [`crate::DeSmuMEMemory::register_read`]. It is chained after the lag frame detection.
+pub(crate) fn set_keypad_read_callback(callback: MemoryCbFnc) {
+    *KEYPAD_READ_CALLBACK.lock().unwrap_or_else(PoisonError::into_inner) = callback;
+}
+
+extern "C" fn keypad_read(addr: c_uint, size: c_int) -> c_bool {
+    POLLED.store(true, Ordering::Relaxed);
+    let callback = *KEYPAD_READ_CALLBACK.lock().unwrap_or_else(PoisonError::into_inner);
+    match callback {
+        Some(callback) => callback(addr, size),
+        None => 1,
+    }
+}
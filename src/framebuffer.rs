@@ -0,0 +1,160 @@
+//! Typed access to the display buffer, see [`Frame`].
+
+use crate::DeSmuMEError;
+
+/// A copy of the display buffer, holding both screens, returned by
+/// [`crate::LoadedDeSmuME::frame`].
+///
+/// Pixels are stored in the native format of DeSmuME, 15 bit BGR (`0bXBBBBBGGGGGRRRRR`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<u16>,
+    width: usize,
+    height: usize
+}
+
+/// One screen of a [`Frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen<'a> {
+    pixels: &'a [u16],
+    width: usize,
+    height: usize
+}
+
+impl Frame {
+    /// Create a frame from the pixels of both screens, the top screen first.
+    /// `width` and `height` are the size of a single screen.
+    /// Additional pixels at the end are ignored.
+    pub fn new(mut pixels: Vec<u16>, width: usize, height: usize) -> Result<Self, DeSmuMEError> {
+        check_len(pixels.len(), width * height * 2)?;
+        pixels.truncate(width * height * 2);
+        Ok(Self { pixels, width, height })
+    }
+
+    /// Returns the width of the screens.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of both screens together.
+    pub fn height(&self) -> usize {
+        self.height * 2
+    }
+
+    pub fn top(&self) -> Screen<'_> {
+        self.screen(0)
+    }
+
+    pub fn bottom(&self) -> Screen<'_> {
+        self.screen(1)
+    }
+
+    /// Returns both screens as one screen, the top screen above the bottom screen.
+    pub fn both(&self) -> Screen<'_> {
+        Screen { pixels: &self.pixels, width: self.width, height: self.height * 2 }
+    }
+
+    /// Returns the raw pixels of both screens.
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    fn screen(&self, index: usize) -> Screen<'_> {
+        let len = self.width * self.height;
+        Screen { pixels: &self.pixels[index * len..(index + 1) * len], width: self.width, height: self.height }
+    }
+}
+
+impl<'a> Screen<'a> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the raw pixels, line by line.
+    pub fn pixels(&self) -> &'a [u16] {
+        self.pixels
+    }
+
+    /// Returns the raw pixel at the given position, or `None` if it is outside of the screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u16> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the color of the pixel at the given position as 8 bit RGB values.
+    pub fn rgb(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        self.pixel(x, y).map(to_rgb888)
+    }
+
+    /// Write the pixels as RGBA color values (4 bytes per pixel) into the start of `buffer`.
+    pub fn to_rgba8_into(&self, buffer: &mut [u8]) -> Result<(), DeSmuMEError> {
+        check_len(buffer.len(), self.pixels.len() * 4)?;
+        for (pixel, out) in self.pixels.iter().zip(buffer.chunks_exact_mut(4)) {
+            let [r, g, b] = to_rgb888(*pixel);
+            out.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+        Ok(())
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut buffer = vec![0; self.pixels.len() * 4];
+        self.to_rgba8_into(&mut buffer).unwrap();
+        buffer
+    }
+
+    /// Write the pixels as RGB color values (3 bytes per pixel) into the start of `buffer`.
+    pub fn to_rgb888_into(&self, buffer: &mut [u8]) -> Result<(), DeSmuMEError> {
+        check_len(buffer.len(), self.pixels.len() * 3)?;
+        for (pixel, out) in self.pixels.iter().zip(buffer.chunks_exact_mut(3)) {
+            out.copy_from_slice(&to_rgb888(*pixel));
+        }
+        Ok(())
+    }
+
+    pub fn to_rgb888(&self) -> Vec<u8> {
+        let mut buffer = vec![0; self.pixels.len() * 3];
+        self.to_rgb888_into(&mut buffer).unwrap();
+        buffer
+    }
+
+    /// Write the pixels as RGB565 values into the start of `buffer`.
+    pub fn to_rgb565_into(&self, buffer: &mut [u16]) -> Result<(), DeSmuMEError> {
+        check_len(buffer.len(), self.pixels.len())?;
+        for (pixel, out) in self.pixels.iter().zip(buffer.iter_mut()) {
+            let (r, g, b) = channels(*pixel);
+            *out = (r as u16) << 11 | ((g << 1 | g >> 4) as u16) << 5 | b as u16;
+        }
+        Ok(())
+    }
+
+    pub fn to_rgb565(&self) -> Vec<u16> {
+        let mut buffer = vec![0; self.pixels.len()];
+        self.to_rgb565_into(&mut buffer).unwrap();
+        buffer
+    }
+}
+
+/// Split a pixel into its 5 bit color channels.
+fn channels(pixel: u16) -> (u8, u8, u8) {
+    ((pixel & 0x1F) as u8, (pixel >> 5 & 0x1F) as u8, (pixel >> 10 & 0x1F) as u8)
+}
+
+fn to_rgb888(pixel: u16) -> [u8; 3] {
+    let (r, g, b) = channels(pixel);
+    [r << 3 | r >> 2, g << 3 | g >> 2, b << 3 | b >> 2]
+}
+
+fn check_len(actual: usize, required: usize) -> Result<(), DeSmuMEError> {
+    if actual < required {
+        Err(DeSmuMEError::BufferTooSmall { required, actual })
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+//! Run the emulator on a dedicated thread and control it through an [`EmulatorHandle`].
+
+#[cfg(feature = "async")]
+mod stream;
+
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::mem::{IndexMove, IndexSet};
+use crate::loaded::EmulatorState;
+use crate::{DeSmuME, DeSmuMEError, LoadedDeSmuME, SpeedMode};
+
+#[cfg(feature = "async")]
+pub use crate::handle::stream::{FrameStream, RgbaFrame};
+
+/// The maximum number of bytes read or written by a single memory request.
+pub const MAX_MEMORY_REQUEST: u32 = 16 * 1024 * 1024;
+
+type Job = Box<dyn FnOnce(&mut EmulatorState) -> Option<EmulatorEvent> + Send>;
+/// Receives every emulated frame, returns false once it is no longer interested.
+type FrameSink = Box<dyn FnMut(&FrameUpdate) -> bool + Send>;
+
+enum Command {
+    Run(Job),
+    SubscribeEvents(Sender<EmulatorEvent>),
+    SubscribeFrames(FrameSink),
+    Shutdown(Sender<()>),
+}
+
+/// Events published by the emulator thread, see [`EmulatorHandle::subscribe_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EmulatorEvent {
+    RomOpened,
+    Paused,
+    Resumed,
+    Reset,
+    SavestateLoaded,
+    /// The emulator thread stopped, no further events will be sent.
+    Shutdown,
+}
+
+/// A frame published by the emulator thread, see [`EmulatorHandle::subscribe_frames`].
+#[derive(Debug, Clone)]
+pub struct FrameUpdate {
+    /// The frame number, see [`LoadedDeSmuME::frame_count`].
+    pub frame: u64,
+    /// Both screens as RGBX color values, see [`LoadedDeSmuME::display_buffer_as_rgbx`].
+    pub rgbx: Arc<Vec<u8>>,
+}
+
+/// Receives the frames published by the emulator thread, see [`EmulatorHandle::subscribe_frames`].
+///
+/// Only the latest frame is kept. If frames are not received fast enough, older ones are skipped.
+pub struct FrameReceiver(Arc<LatestFrame>);
+
+#[derive(Default)]
+struct LatestFrame {
+    slot: Mutex<FrameSlot>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct FrameSlot {
+    frame: Option<FrameUpdate>,
+    /// Whether the emulator thread stopped publishing frames.
+    closed: bool,
+}
+
+impl FrameReceiver {
+    /// Wait for the next frame. Fails with [`DeSmuMEError::EmulatorThreadStopped`] once the
+    /// emulator thread stopped and the last frame was received.
+    pub fn recv(&self) -> Result<FrameUpdate, DeSmuMEError> {
+        let mut slot = self.0.lock();
+        loop {
+            if let Some(frame) = Self::take(&mut slot)? {
+                return Ok(frame);
+            }
+            slot = self.0.ready.wait(slot).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Wait for the next frame for at most `timeout`, returns `None` if no frame was published.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<FrameUpdate>, DeSmuMEError> {
+        let deadline = Instant::now() + timeout;
+        let mut slot = self.0.lock();
+        loop {
+            if let Some(frame) = Self::take(&mut slot)? {
+                return Ok(Some(frame));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            slot = self.0.ready.wait_timeout(slot, remaining).unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+
+    /// Returns the next frame, if one was published since the last one was received.
+    pub fn try_recv(&self) -> Result<Option<FrameUpdate>, DeSmuMEError> {
+        Self::take(&mut self.0.lock())
+    }
+
+    fn take(slot: &mut FrameSlot) -> Result<Option<FrameUpdate>, DeSmuMEError> {
+        match slot.frame.take() {
+            Some(frame) => Ok(Some(frame)),
+            None if slot.closed => Err(DeSmuMEError::EmulatorThreadStopped),
+            None => Ok(None),
+        }
+    }
+}
+
+impl LatestFrame {
+    fn lock(&self) -> MutexGuard<'_, FrameSlot> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The emulator thread's side of a [`FrameReceiver`], which closes it when dropped.
+struct FramePublisher(Arc<LatestFrame>);
+
+impl FramePublisher {
+    /// Replace the latest frame. Returns false once the receiver was dropped.
+    fn publish(&self, frame: &FrameUpdate) -> bool {
+        if Arc::strong_count(&self.0) == 1 {
+            return false;
+        }
+        self.0.lock().frame = Some(frame.clone());
+        self.0.ready.notify_all();
+        true
+    }
+}
+
+impl Drop for FramePublisher {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.ready.notify_all();
+    }
+}
+
+/// Handle to an emulator running on its own thread.
+///
+/// While a game is running, the thread emulates frames at the speed of the DS and publishes them
+/// to subscribers. Commands sent through the handle are processed between frames.
+///
+/// The handle can be cloned and shared between threads. The emulator thread stops when
+/// [`EmulatorHandle::shutdown`] is called or when all handles are dropped. Afterwards all
+/// methods return [`DeSmuMEError::EmulatorThreadStopped`].
+#[derive(Clone)]
+pub struct EmulatorHandle {
+    commands: Sender<Command>,
+}
+
+impl EmulatorHandle {
+    /// Initialize DeSmuME on a new thread. Fails with the error of [`DeSmuME::init`].
+    pub fn spawn() -> Result<Self, DeSmuMEError> {
+        let (commands, receiver) = mpsc::channel();
+        let (init_sender, init_receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("desmume".to_owned())
+            .spawn(move || {
+                match DeSmuME::init() {
+                    Ok(mut emu) => {
+                        // Can't fail for the real-time mode.
+                        let _ = emu.set_speed_mode(SpeedMode::RealTime);
+                        let _ = init_sender.send(Ok(()));
+                        Worker { state: EmulatorState::Idle(emu), events: vec![], frames: vec![] }.run(receiver);
+                    }
+                    Err(e) => {
+                        let _ = init_sender.send(Err(e));
+                    }
+                }
+            })?;
+        init_receiver.recv().map_err(|_| DeSmuMEError::EmulatorThreadStopped)??;
+        Ok(Self { commands })
+    }
+
+    /// Run `f` with the emulator on the emulator thread and return its result.
+    /// This gives access to everything not covered by the other methods of the handle.
+    ///
+    /// Fails with [`DeSmuMEError::NoRomLoaded`] if no ROM was opened yet.
+    pub fn execute<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut LoadedDeSmuME) -> R + Send + 'static
+    {
+        self.request(move |state| (state.loaded().map(f), None))?
+    }
+
+    /// Like [`EmulatorHandle::execute`], but `f` is also run if no ROM is loaded.
+    pub fn execute_any<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut DeSmuME) -> R + Send + 'static
+    {
+        self.request(move |state| (state.emu().map(f), None))?
+    }
+
+    /// Open a ROM, see [`LoadedDeSmuME::open_path`].
+    pub fn open(&self, path: impl Into<PathBuf>, auto_resume: bool) -> Result<(), DeSmuMEError> {
+        let path = path.into();
+        self.request(move |state| {
+            let result = state.open(|emu| emu.open_path(&path, auto_resume));
+            let event = result.is_ok().then_some(EmulatorEvent::RomOpened);
+            (result, event)
+        })?
+    }
+
+    /// Pause the emulator.
+    pub fn pause(&self) -> Result<(), DeSmuMEError> {
+        self.request_loaded(|emu| (emu.pause(), Some(EmulatorEvent::Paused)))
+    }
+
+    /// Resume the emulator, see [`LoadedDeSmuME::resume`].
+    pub fn resume(&self, keep_keypad: bool) -> Result<(), DeSmuMEError> {
+        self.request_loaded(move |emu| (emu.resume(keep_keypad), Some(EmulatorEvent::Resumed)))
+    }
+
+    /// Reset the emulator / restart the current game.
+    pub fn reset(&self) -> Result<(), DeSmuMEError> {
+        self.request_loaded(|emu| (emu.reset(), Some(EmulatorEvent::Reset)))
+    }
+
+    /// Set the pressed keys, see [`crate::DeSmuMEInput::keypad_update`].
+    pub fn set_keypad(&self, keys: u16) -> Result<(), DeSmuMEError> {
+        self.execute_any(move |emu| emu.input_mut().keypad_update(keys))
+    }
+
+    /// Set how fast the emulator thread emulates frames, see [`DeSmuME::set_speed_mode`].
+    /// The emulator thread starts in [`SpeedMode::RealTime`].
+    pub fn set_speed_mode(&self, mode: SpeedMode) -> Result<(), DeSmuMEError> {
+        self.execute_any(move |emu| emu.set_speed_mode(mode))?
+    }
+
+    /// Returns the measured emulation speed in percent, see [`DeSmuME::speed_percent`].
+    pub fn speed_percent(&self) -> Result<f64, DeSmuMEError> {
+        self.execute_any(|emu| emu.speed_percent())
+    }
+
+    /// Touch the touch screen at the given position.
+    pub fn touch_set_pos(&self, x: u16, y: u16) -> Result<(), DeSmuMEError> {
+        self.execute_any(move |emu| emu.input_mut().touch_set_pos(x, y))
+    }
+
+    /// Release the touch screen.
+    pub fn touch_release(&self) -> Result<(), DeSmuMEError> {
+        self.execute_any(|emu| emu.input_mut().touch_release())
+    }
+
+    /// Read `len` bytes of memory starting at `addr`. At most [`MAX_MEMORY_REQUEST`] bytes can be
+    /// read at once.
+    pub fn read_memory(&self, addr: u32, len: u32) -> Result<Vec<u8>, DeSmuMEError> {
+        self.execute(move |emu| read_bytes(emu, addr, len))?
+    }
+
+    /// Write `data` to the memory starting at `addr`. At most [`MAX_MEMORY_REQUEST`] bytes can be
+    /// written at once.
+    pub fn write_memory(&self, addr: u32, data: Vec<u8>) -> Result<(), DeSmuMEError> {
+        self.execute(move |emu| write_bytes(emu, addr, &data))?
+    }
+
+    /// Save the current game state, see [`crate::DeSmuMESavestate::save_to_vec`].
+    pub fn save_state(&self) -> Result<Vec<u8>, DeSmuMEError> {
+        self.execute(|emu| emu.savestate_mut().save_to_vec())?
+    }
+
+    /// Load a savestate, see [`crate::DeSmuMESavestate::load_from_slice`].
+    pub fn load_state(&self, data: Vec<u8>) -> Result<(), DeSmuMEError> {
+        self.request(load_job(move |emu| emu.savestate_mut().load_from_slice(&data)))?
+    }
+
+    /// Save the current game state to a savestate slot.
+    pub fn save_slot(&self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        self.execute(move |emu| emu.savestate_mut().save(slot_id))?
+    }
+
+    /// Load the savestate in a savestate slot.
+    pub fn load_slot(&self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        self.request(load_job(move |emu| emu.savestate_mut().load(slot_id)))?
+    }
+
+    /// Subscribe to the events of the emulator thread.
+    pub fn subscribe_events(&self) -> Result<Receiver<EmulatorEvent>, DeSmuMEError> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Command::SubscribeEvents(sender))?;
+        Ok(receiver)
+    }
+
+    /// Subscribe to the frames emulated by the emulator thread. Only the latest frame is kept
+    /// for the receiver, so a receiver that is not drained does not hold on to older frames.
+    pub fn subscribe_frames(&self) -> Result<FrameReceiver, DeSmuMEError> {
+        let latest = Arc::new(LatestFrame::default());
+        let publisher = FramePublisher(latest.clone());
+        self.send(Command::SubscribeFrames(Box::new(move |frame| publisher.publish(frame))))?;
+        Ok(FrameReceiver(latest))
+    }
+
+    /// Stop the emulator thread and wait until the emulator is dropped,
+    /// after which [`DeSmuME::init`] can be used again.
+    pub fn shutdown(&self) -> Result<(), DeSmuMEError> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Command::Shutdown(sender))?;
+        receiver.recv().map_err(|_| DeSmuMEError::EmulatorThreadStopped)
+    }
+
+    /// Like [`EmulatorHandle::request`], but fails if no ROM is loaded.
+    fn request_loaded<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut LoadedDeSmuME) -> (R, Option<EmulatorEvent>) + Send + 'static
+    {
+        self.request(move |state| match state.loaded() {
+            Ok(emu) => {
+                let (result, event) = f(emu);
+                (Ok(result), event)
+            }
+            Err(e) => (Err(e), None),
+        })?
+    }
+
+    fn request<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut EmulatorState) -> (R, Option<EmulatorEvent>) + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.send_job(f, move |result| { let _ = sender.send(result); })?;
+        receiver.recv().map_err(|_| DeSmuMEError::EmulatorThreadStopped)
+    }
+
+    /// Send `f` to the emulator thread, which passes its result to `reply`
+    /// and publishes the returned event.
+    fn send_job<R, F, S>(&self, f: F, reply: S) -> Result<(), DeSmuMEError>
+    where
+        F: FnOnce(&mut EmulatorState) -> (R, Option<EmulatorEvent>) + Send + 'static,
+        S: FnOnce(R) + Send + 'static
+    {
+        self.send(Command::Run(Box::new(move |state| {
+            let (result, event) = f(state);
+            reply(result);
+            event
+        })))
+    }
+
+    fn send(&self, command: Command) -> Result<(), DeSmuMEError> {
+        self.commands.send(command).map_err(|_| DeSmuMEError::EmulatorThreadStopped)
+    }
+}
+
+/// State of the emulator thread.
+struct Worker {
+    state: EmulatorState,
+    events: Vec<Sender<EmulatorEvent>>,
+    frames: Vec<FrameSink>,
+}
+
+impl Worker {
+    fn run(mut self, commands: Receiver<Command>) {
+        let shutdown_reply = loop {
+            let command = if self.is_running() {
+                // Wait for commands until the next frame is due, instead of sleeping in `cycle`.
+                let remaining = self.state.emu().map(|emu| emu.limiter.remaining()).unwrap_or_default();
+                if remaining.is_zero() {
+                    match commands.try_recv() {
+                        Ok(command) => command,
+                        Err(TryRecvError::Empty) => {
+                            self.frame();
+                            continue;
+                        }
+                        Err(TryRecvError::Disconnected) => break None,
+                    }
+                } else {
+                    match commands.recv_timeout(remaining) {
+                        Ok(command) => command,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break None,
+                    }
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => break None,
+                }
+            };
+            match command {
+                Command::Run(job) => {
+                    if let Some(event) = job(&mut self.state) {
+                        self.publish(event);
+                    }
+                }
+                Command::SubscribeEvents(sender) => self.events.push(sender),
+                Command::SubscribeFrames(sink) => self.frames.push(sink),
+                Command::Shutdown(reply) => break Some(reply),
+            }
+        };
+        self.publish(EmulatorEvent::Shutdown);
+        drop(self.state);
+        if let Some(reply) = shutdown_reply {
+            let _ = reply.send(());
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(&self.state, EmulatorState::Loaded(emu) if emu.is_running())
+    }
+
+    fn frame(&mut self) {
+        let Ok(emu) = self.state.loaded() else {
+            return;
+        };
+        emu.cycle();
+        if self.frames.is_empty() {
+            return;
+        }
+        let update = FrameUpdate {
+            frame: emu.frame_count(),
+            rgbx: Arc::new(emu.display_buffer_as_rgbx()),
+        };
+        self.frames.retain_mut(|sink| sink(&update));
+    }
+
+    fn publish(&mut self, event: EmulatorEvent) {
+        self.events.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+/// A job loading a savestate with `load`, which publishes [`EmulatorEvent::SavestateLoaded`] if
+/// it succeeds. Shared by the sync and async savestate requests.
+pub(crate) fn load_job<F>(load: F) -> impl FnOnce(&mut EmulatorState) -> (Result<(), DeSmuMEError>, Option<EmulatorEvent>) + Send + 'static
+where
+    F: FnOnce(&mut LoadedDeSmuME) -> Result<(), DeSmuMEError> + Send + 'static
+{
+    move |state| {
+        let result = state.loaded().and_then(load);
+        let event = result.is_ok().then_some(EmulatorEvent::SavestateLoaded);
+        (result, event)
+    }
+}
+
+/// The addresses of a memory request, which must neither be too large nor wrap around.
+fn memory_range(addr: u32, len: usize) -> Result<Range<u32>, DeSmuMEError> {
+    if len > MAX_MEMORY_REQUEST as usize {
+        return Err(DeSmuMEError::MemoryRequestTooLarge { len, max: MAX_MEMORY_REQUEST });
+    }
+    let end = addr.checked_add(len as u32).ok_or(DeSmuMEError::InvalidAddress(addr))?;
+    Ok(addr..end)
+}
+
+pub(crate) fn read_bytes(emu: &LoadedDeSmuME, addr: u32, len: u32) -> Result<Vec<u8>, DeSmuMEError> {
+    let range = memory_range(addr, len as usize)?;
+    if range.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(emu.memory().u8().index_move(range))
+}
+
+pub(crate) fn write_bytes(emu: &mut LoadedDeSmuME, addr: u32, data: &Vec<u8>) -> Result<(), DeSmuMEError> {
+    let range = memory_range(addr, data.len())?;
+    if !range.is_empty() {
+        emu.memory_mut().u8_mut().index_set(range, data);
+    }
+    Ok(())
+}
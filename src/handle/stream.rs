@@ -0,0 +1,141 @@
+//! Async access to an [`EmulatorHandle`], enabled with the `async` feature.
+//!
+//! The futures only wait for the emulator thread, so they work with any async runtime.
+
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use futures::channel::{mpsc, oneshot};
+use futures::Stream;
+use crate::handle::{load_job, read_bytes, write_bytes, Command, EmulatorEvent, EmulatorHandle};
+use crate::loaded::EmulatorState;
+use crate::{DeSmuMEError, LoadedDeSmuME};
+
+/// Number of frames buffered for a [`FrameStream`] before further frames are skipped.
+const STREAM_BUFFER: usize = 4;
+
+/// A frame yielded by a [`FrameStream`].
+#[derive(Debug, Clone)]
+pub struct RgbaFrame {
+    /// The frame number, see [`LoadedDeSmuME::frame_count`].
+    pub frame: u64,
+    /// Both screens as RGBA color values.
+    pub rgba: Arc<Vec<u8>>,
+}
+
+/// Stream of the frames emulated by the emulator thread, see [`EmulatorHandle::frame_stream`].
+///
+/// If the stream is not polled fast enough, frames are skipped.
+pub struct FrameStream(mpsc::Receiver<RgbaFrame>);
+
+impl Stream for FrameStream {
+    type Item = RgbaFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl EmulatorHandle {
+    /// Returns a stream of the emulated frames. The stream ends when the emulator thread stops.
+    pub fn frame_stream(&self) -> Result<FrameStream, DeSmuMEError> {
+        let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        self.send(Command::SubscribeFrames(Box::new(move |update| {
+            if sender.is_closed() {
+                return false;
+            }
+            let mut rgba = update.rgbx.as_ref().clone();
+            rgba.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 0xFF);
+            // A full buffer only means that this frame is skipped.
+            let _ = sender.try_send(RgbaFrame { frame: update.frame, rgba: Arc::new(rgba) });
+            true
+        })))?;
+        Ok(FrameStream(receiver))
+    }
+
+    /// Async version of [`EmulatorHandle::execute`].
+    pub async fn execute_async<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut LoadedDeSmuME) -> R + Send + 'static
+    {
+        self.request_async(move |state| (state.loaded().map(f), None)).await?
+    }
+
+    /// Async version of [`EmulatorHandle::read_memory`].
+    pub async fn read_memory_async(&self, addr: u32, len: u32) -> Result<Vec<u8>, DeSmuMEError> {
+        self.execute_async(move |emu| read_bytes(emu, addr, len)).await?
+    }
+
+    /// Async version of [`EmulatorHandle::write_memory`].
+    pub async fn write_memory_async(&self, addr: u32, data: Vec<u8>) -> Result<(), DeSmuMEError> {
+        self.execute_async(move |emu| write_bytes(emu, addr, &data)).await?
+    }
+
+    /// Wait until the `len` bytes of memory at `addr` change and return the new value.
+    /// The memory is compared after every emulated frame, so this only resolves while a game
+    /// is running.
+    pub async fn memory_changed(&self, addr: u32, len: u32) -> Result<Vec<u8>, DeSmuMEError> {
+        let (sender, receiver) = oneshot::channel();
+        self.execute_async(move |emu| {
+            let initial = read_bytes(emu, addr, len)?;
+            let mut sender = Some(sender);
+            // The hook removes itself by dropping its own handle.
+            let own_handle = Rc::new(RefCell::new(None));
+            let hook_handle = own_handle.clone();
+            let handle = emu.on_frame_end(move |emu| {
+                let done = match &sender {
+                    Some(waiting) if !waiting.is_canceled() => {
+                        // The range was already checked when reading the initial value.
+                        match read_bytes(emu, addr, len) {
+                            Ok(value) if value != initial => {
+                                let _ = sender.take().unwrap().send(value);
+                                true
+                            }
+                            _ => false
+                        }
+                    }
+                    _ => true,
+                };
+                if done {
+                    hook_handle.borrow_mut().take();
+                }
+            });
+            *own_handle.borrow_mut() = Some(handle);
+            Ok::<_, DeSmuMEError>(())
+        }).await??;
+        receiver.await.map_err(|_| DeSmuMEError::EmulatorThreadStopped)
+    }
+
+    /// Async version of [`EmulatorHandle::save_state`].
+    pub async fn save_state_async(&self) -> Result<Vec<u8>, DeSmuMEError> {
+        self.execute_async(|emu| emu.savestate_mut().save_to_vec()).await?
+    }
+
+    /// Async version of [`EmulatorHandle::load_state`].
+    pub async fn load_state_async(&self, data: Vec<u8>) -> Result<(), DeSmuMEError> {
+        self.request_async(load_job(move |emu| emu.savestate_mut().load_from_slice(&data))).await?
+    }
+
+    /// Async version of [`EmulatorHandle::save_slot`].
+    pub async fn save_slot_async(&self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        self.execute_async(move |emu| emu.savestate_mut().save(slot_id)).await?
+    }
+
+    /// Async version of [`EmulatorHandle::load_slot`].
+    pub async fn load_slot_async(&self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        self.request_async(load_job(move |emu| emu.savestate_mut().load(slot_id))).await?
+    }
+
+    async fn request_async<R, F>(&self, f: F) -> Result<R, DeSmuMEError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut EmulatorState) -> (R, Option<EmulatorEvent>) + Send + 'static
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.send_job(f, move |result| { let _ = sender.send(result); })?;
+        receiver.await.map_err(|_| DeSmuMEError::EmulatorThreadStopped)
+    }
+}
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::{Rc, Weak};
+use crate::LoadedDeSmuME;
+
+type Hook = Box<dyn FnMut(&mut LoadedDeSmuME)>;
+
+/// The points in the emulator loop hooks can be registered for.
+#[derive(Clone, Copy)]
+pub(crate) enum HookKind {
+    FrameStart,
+    FrameEnd,
+}
+
+/// Closures registered with [`LoadedDeSmuME::on_frame_start`] and [`LoadedDeSmuME::on_frame_end`].
+#[derive(Default)]
+pub(crate) struct HookRegistry {
+    next_id: u64,
+    frame_start: Vec<(u64, Hook)>,
+    frame_end: Vec<(u64, Hook)>,
+    /// Hooks that were removed while the innermost run was in progress.
+    removed: Vec<u64>,
+}
+
+impl HookRegistry {
+    pub(crate) fn add(registry: &Rc<RefCell<Self>>, kind: HookKind, hook: Hook) -> HookHandle {
+        let mut this = registry.borrow_mut();
+        let id = this.next_id;
+        this.next_id += 1;
+        this.list_mut(kind).push((id, hook));
+        HookHandle { registry: Rc::downgrade(registry), id }
+    }
+
+    /// Run all hooks of the given kind.
+    ///
+    /// The hooks are taken out of the registry while they run, so that they can freely use the
+    /// emulator, including registering new hooks, dropping handles and emulating frames, which
+    /// runs hooks again.
+    pub(crate) fn run(registry: &Rc<RefCell<Self>>, kind: HookKind, emu: &mut LoadedDeSmuME) {
+        let mut hooks = mem::take(registry.borrow_mut().list_mut(kind));
+        if hooks.is_empty() {
+            return;
+        }
+        // Removals during an outer run are kept aside until this run is done.
+        let outer_removed = mem::take(&mut registry.borrow_mut().removed);
+        for (id, hook) in hooks.iter_mut() {
+            // Skip hooks removed by a hook that ran before them.
+            if !registry.borrow().removed.contains(id) {
+                hook(emu);
+            }
+        }
+        let mut this = registry.borrow_mut();
+        let removed = mem::replace(&mut this.removed, outer_removed);
+        let (own, outer): (Vec<u64>, Vec<u64>) = removed.into_iter()
+            .partition(|id| hooks.iter().any(|(hook_id, _)| hook_id == id));
+        // Hooks of outer runs removed during this run are dropped when their run is done.
+        this.removed.extend(outer);
+        hooks.retain(|(id, _)| !own.contains(id));
+        let added = mem::replace(this.list_mut(kind), hooks);
+        this.list_mut(kind).extend(added);
+    }
+
+    fn remove(&mut self, id: u64) {
+        for list in [&mut self.frame_start, &mut self.frame_end] {
+            if let Some(pos) = list.iter().position(|(hook_id, _)| *hook_id == id) {
+                drop(list.remove(pos));
+                return;
+            }
+        }
+        self.removed.push(id);
+    }
+
+    fn list_mut(&mut self, kind: HookKind) -> &mut Vec<(u64, Hook)> {
+        match kind {
+            HookKind::FrameStart => &mut self.frame_start,
+            HookKind::FrameEnd => &mut self.frame_end,
+        }
+    }
+}
+
+/// Handle to a hook registered with [`LoadedDeSmuME::on_frame_start`] or [`LoadedDeSmuME::on_frame_end`].
+/// The hook is removed when the handle is dropped.
+#[must_use = "the hook is removed when the handle is dropped"]
+pub struct HookHandle {
+    registry: Weak<RefCell<HookRegistry>>,
+    id: u64,
+}
+
+impl HookHandle {
+    /// Keep the hook registered for as long as the emulator exists.
+    pub fn forget(self) {
+        mem::forget(self)
+    }
+}
+
+impl Drop for HookHandle {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.borrow_mut().remove(self.id);
+        }
+    }
+}
@@ -3,7 +3,9 @@ use crate::ffi::*;
 
 /// Manage input processing for the emulator.
 pub struct DeSmuMEInput {
-    pub(crate) joystick_was_init: bool
+    pub(crate) joystick_was_init: bool,
+    /// DeSmuME does not allow reading back the touch position, so it is tracked here.
+    pub(crate) touch_pos: Option<(u16, u16)>
 }
 
 impl DeSmuMEInput {
@@ -97,10 +99,18 @@ impl DeSmuMEInput {
     
     pub fn touch_set_pos(&mut self, x: u16, y: u16) {
         unsafe { desmume_input_set_touch_pos(x, y) }
+        self.touch_pos = Some((x, y));
     }
 
     pub fn touch_release(&mut self) {
         unsafe { desmume_input_release_touch() }
+        self.touch_pos = None;
+    }
+
+    /// Returns the touch position last set with `touch_set_pos`, or `None` if the
+    /// touchscreen is released. Touches made in the SDL window are not tracked.
+    pub fn touch_get_pos(&self) -> Option<(u16, u16)> {
+        self.touch_pos
     }
 }
 
@@ -1,32 +1,63 @@
-use std::ffi::CString;
 use std::marker::PhantomData;
-use std::ptr::slice_from_raw_parts;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
 use crate::ffi::*;
+use crate::limiter::FrameLimiter;
 #[macro_use] mod macros;
 
+pub mod backup;
+pub mod compositor;
 mod ffi;
+mod frame;
+pub mod framebuffer;
+pub mod handle;
+mod hooks;
+pub mod limiter;
+mod loaded;
 pub mod mem;
 mod movie;
-mod savestate;
+pub mod remote;
+pub mod rewind;
+pub mod savestate;
+pub mod screenshot;
 pub mod input;
+pub mod rom;
 mod sdl_window;
+pub mod video;
 mod err;
 
-pub use crate::err::DeSmuMEError;
+pub use crate::backup::{DeSmuMEBackup, SaveType};
+pub use crate::compositor::Compositor;
+pub use crate::err::{DeSmuMEError, OpenError};
+pub use crate::frame::FrameCounters;
+pub use crate::framebuffer::{Frame, Screen};
+pub use crate::handle::{EmulatorEvent, EmulatorHandle, FrameReceiver, FrameUpdate};
+#[cfg(feature = "async")]
+pub use crate::handle::{FrameStream, RgbaFrame};
+pub use crate::hooks::HookHandle;
 pub use crate::input::DeSmuMEInput;
+pub use crate::limiter::SpeedMode;
+pub use crate::loaded::LoadedDeSmuME;
 pub use crate::mem::DeSmuMEMemory;
 pub use crate::movie::DeSmuMEMovie;
-pub use crate::savestate::DeSmuMESavestate;
+pub use crate::remote::RemoteDeSmuME;
+pub use crate::rewind::RewindConfig;
+pub use crate::ffi::SimpleDate;
+pub use crate::screenshot::ScreenSelection;
+pub use crate::savestate::{DeSmuMESavestate, SavestateMetadata, SlotInfo};
 pub use crate::sdl_window::DeSmuMESdlWindow;
+pub use crate::video::VideoFormat;
 
-static mut WAS_EVER_ALREADY_INITIALIZED: bool = false;
-static mut ALREADY_INITIALIZED: bool = false;
+/// Lifecycle of the native emulator, shared by all threads.
+static INSTANCE: Mutex<InstanceState> = Mutex::new(InstanceState { alive: false, native: NativeState::Uninit });
 pub const SCREEN_WIDTH: usize = ffi::GPU_FRAMEBUFFER_NATIVE_WIDTH;
 pub const SCREEN_HEIGHT: usize = ffi::GPU_FRAMEBUFFER_NATIVE_HEIGHT;
 pub const SCREEN_HEIGHT_BOTH: usize = SCREEN_HEIGHT * 2;
 pub const SCREEN_PIXEL_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
 pub const SCREEN_PIXEL_SIZE_BOTH: usize = SCREEN_WIDTH * SCREEN_HEIGHT_BOTH;
 pub const NB_STATES: usize = 10;
+/// Address of the copy of the cartridge header the firmware places in main RAM.
+const RAM_HEADER_ADDR: u32 = 0x027FFE00;
 
 /// Firmware language
 #[repr(u8)]
@@ -39,13 +70,17 @@ pub enum Language {
     Spanish = 5
 }
 
-/// The DeSmuME emulator.
+/// The DeSmuME emulator, without a ROM loaded.
+///
+/// Open a ROM to get a [`LoadedDeSmuME`], which provides everything that needs a loaded game.
+///
+/// DeSmuME has global state, so the emulator is neither `Send` nor `Sync`. Use
+/// [`EmulatorHandle`] to control it from other threads.
 pub struct DeSmuME {
     input: DeSmuMEInput,
-    memory: DeSmuMEMemory,
-    movie: DeSmuMEMovie,
-    savestate: DeSmuMESavestate,
-    window: Option<DeSmuMESdlWindow>
+    window: Option<DeSmuMESdlWindow>,
+    limiter: FrameLimiter,
+    _not_send: PhantomData<*mut ()>
 }
 
 impl DeSmuME {
@@ -58,27 +93,30 @@ impl DeSmuME {
     ///     running instances.
     ///     Additionally note that DeSmuME is not free'd at the moment when dropping it, this is
     ///     to allow future instances of DeSmuME being created again later on. Use [`free_desmume`]
-    ///     to manually free resources created by the library.
+    ///     to manually free resources created by the library, after which this returns
+    ///     [`DeSmuMEError::Freed`].
     pub fn init() -> Result<DeSmuME, DeSmuMEError> {
-        unsafe {
-            if ALREADY_INITIALIZED {
-                return Err(DeSmuMEError::AlreadyInit)
-            }
-            ALREADY_INITIALIZED = true;
-            desmume_set_savetype(0);
-            if !WAS_EVER_ALREADY_INITIALIZED {
+        let mut instance = lock_instance();
+        if instance.alive {
+            return Err(DeSmuMEError::AlreadyInit)
+        }
+        match instance.native {
+            NativeState::Freed => return Err(DeSmuMEError::Freed),
+            NativeState::Uninit => unsafe {
+                desmume_set_savetype(SaveType::Auto as c_int);
                 if desmume_init() < 0 {
                     return Err(DeSmuMEError::FailedInit)
                 }
-                WAS_EVER_ALREADY_INITIALIZED = true;
-            }
+                instance.native = NativeState::Ready;
+            },
+            NativeState::Ready => unsafe { desmume_set_savetype(SaveType::Auto as c_int) }
         }
+        instance.alive = true;
         Ok(Self {
-            input: DeSmuMEInput {joystick_was_init: false},
-            memory: DeSmuMEMemory(PhantomData),
-            movie: DeSmuMEMovie(PhantomData),
-            savestate: DeSmuMESavestate(PhantomData),
-            window: None
+            input: DeSmuMEInput {joystick_was_init: false, touch_pos: None},
+            window: None,
+            limiter: FrameLimiter::default(),
+            _not_send: PhantomData
         })
     }
 
@@ -90,90 +128,67 @@ impl DeSmuME {
         &mut self.input
     }
 
-    pub fn memory(&self) -> &DeSmuMEMemory {
-        &self.memory
-    }
-
-    pub fn memory_mut(&mut self) -> &mut DeSmuMEMemory {
-        &mut self.memory
-    }
-
-    pub fn movie(&self) -> &DeSmuMEMovie {
-        &self.movie
+    /// Set the current firmware language.
+    pub fn set_language(&mut self, lang: Language) {
+        unsafe { desmume_set_language(lang as u8) }
     }
 
-    pub fn movie_mut(&mut self) -> &mut DeSmuMEMovie {
-        &mut self.movie
+    /// Set how fast [`LoadedDeSmuME::cycle`] emulates frames. In throttled modes `cycle`
+    /// sleeps until the next frame is due. The default is [`SpeedMode::Unthrottled`].
+    ///
+    /// Returns [`DeSmuMEError::InvalidSpeed`] for a [`SpeedMode::Speed`] outside of
+    /// [`limiter::MIN_SPEED`] and [`limiter::MAX_SPEED`].
+    pub fn set_speed_mode(&mut self, mode: SpeedMode) -> Result<(), DeSmuMEError> {
+        self.limiter.set_mode(mode)
     }
 
-    pub fn savestate(&self) -> &DeSmuMESavestate {
-        &self.savestate
+    pub fn speed_mode(&self) -> SpeedMode {
+        self.limiter.mode()
     }
 
-    pub fn savestate_mut(&mut self) -> &mut DeSmuMESavestate {
-        &mut self.savestate
+    /// Returns the number of frames emulated per second, measured over the last 60 frames.
+    pub fn fps(&self) -> f64 {
+        self.limiter.fps()
     }
 
-    /// Set the current firmware language.
-    pub fn set_language(&mut self, lang: Language) {
-        unsafe { desmume_set_language(lang as u8) }
+    /// Returns the emulation speed in percent of the speed of the DS, see [`DeSmuME::fps`].
+    pub fn speed_percent(&self) -> f64 {
+        self.limiter.fps() / limiter::FRAME_RATE * 100.0
     }
 
-    /// Open a ROM by file name.
+    /// Open a ROM by file name and return the emulator with the ROM loaded.
     ///
     /// If `auto_resume` is true, the emulator will automatically begin emulating the game.
     /// Otherwise the emulator is paused and you may call `resume` to unpause it.
-    pub fn open(&mut self, file_name: &str, auto_resume: bool) -> Result<(), DeSmuMEError> {
-        unsafe {
-            if desmume_open(CString::new(file_name)?.as_ptr()) < 0 {
-                return Err(DeSmuMEError::FailedOpen);
-            }
-        }
-        if auto_resume {
-            self.resume(false);
-        }
-        Ok(())
-    }
-
-    /// Set the type of the SRAM. Please see the DeSmuME documentation for possible values.
-    /// 0 is auto-detect and set by default.
-    pub fn set_savetype(&mut self, value: u8) {
-        // TODO: Enum?
-        unsafe { desmume_set_savetype(value as c_int) }
-    }
-
-    /// Pause the emulator.
-    pub fn pause(&mut self) {
-        unsafe { desmume_pause() }
-    }
-
-    /// Resume / unpause the emulator. This will reset the keypad (release all keys),
-    /// except if `keep_keypad` is true.
-    pub fn resume(&mut self, keep_keypad: bool) {
-        if keep_keypad {
-            self.input.keypad_update(0);
-        }
-        unsafe { desmume_resume(); }
+    ///
+    /// If opening the ROM fails, the emulator is returned as part of the [`OpenError`].
+    pub fn open(self, file_name: &str, auto_resume: bool) -> Result<LoadedDeSmuME, OpenError> {
+        LoadedDeSmuME::open_with(self, |emu| emu.open(file_name, auto_resume))
     }
 
-    /// Resets the emulator / restarts the current game.
-    pub fn reset(&mut self) {
-        unsafe { desmume_reset(); }
+    /// Open a ROM by path, see [`LoadedDeSmuME::open_path`].
+    pub fn open_path(self, path: &Path, auto_resume: bool) -> Result<LoadedDeSmuME, OpenError> {
+        LoadedDeSmuME::open_with(self, |emu| emu.open_path(path, auto_resume))
     }
 
-    /// Returns `true`, if a game is loaded and the emulator is running (not paused).
-    pub fn is_running(&self) -> bool {
-        unsafe { desmume_running() > 0 }
+    /// Open a ROM by path with the given backup memory, see [`LoadedDeSmuME::open_with_backup`].
+    pub fn open_with_backup(self, path: &Path, backup: &[u8], auto_resume: bool) -> Result<LoadedDeSmuME, OpenError> {
+        LoadedDeSmuME::open_with(self, |emu| emu.open_with_backup(path, backup, auto_resume))
     }
 
-    /// Tell the emulator to skip the next frame.
-    pub fn skip_next_frame(&mut self) {
-        unsafe { desmume_skip_next_frame() }
+    /// Open a ROM from an in-memory ROM image, see [`LoadedDeSmuME::open_bytes`].
+    pub fn open_bytes(self, rom: &[u8], auto_resume: bool) -> Result<LoadedDeSmuME, OpenError> {
+        LoadedDeSmuME::open_with(self, |emu| emu.open_bytes(rom, auto_resume))
     }
 
-    /// Cycle one game cycle / frame.
-    pub fn cycle(&mut self) {
-        unsafe { desmume_cycle(self.input.joystick_was_init as c_bool) }
+    /// Set the type of the SRAM used for the ROMs opened by this emulator.
+    /// [`SaveType::Auto`] is set by default.
+    ///
+    /// The save type only has an effect when opening a ROM, so this consumes the emulator and
+    /// is not available on a [`LoadedDeSmuME`].
+    pub fn with_savetype(self, value: SaveType) -> Self {
+        unsafe { desmume_set_savetype(value as c_int) }
+        self
     }
 
     /// Returns `true`, if OpenGL is available for rendering.
@@ -197,31 +212,6 @@ impl DeSmuME {
         Ok(self.window.as_mut().unwrap())
     }
 
-    /// Return the display buffer in the internal format.
-    /// You probably want to use display_buffer_as_rgbx instead.
-    pub fn display_buffer(&self) -> &[u16] {
-        unsafe {
-            &*slice_from_raw_parts(desmume_draw_raw(), ffi::FRAMEBUFFER_SIZE)
-        }
-    }
-
-    /// Fill in the display buffer as RGBX color values,
-    /// see the screen size constants for how many pixels make up lines.
-    ///
-    /// # Safety
-    /// The slice passed in must hold `SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4` bytes.
-    pub unsafe fn display_buffer_as_rgbx_into(&self, buffer: &mut [u8]) {
-        desmume_draw_raw_as_rgbx(buffer.as_mut_ptr());
-    }
-
-    /// Return the display buffer as RGBX color values,
-    /// see the screen size constants for how many pixels make up lines.
-    pub fn display_buffer_as_rgbx(&self) -> Vec<u8> {
-        let mut buff = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4];
-        unsafe { self.display_buffer_as_rgbx_into(&mut buff) }
-        buff
-    }
-
     /// Get the current SDL tick number.
     pub fn get_ticks(&self) -> u32 {
         unsafe { desmume_sdl_get_ticks() as u32 }
@@ -260,13 +250,10 @@ impl DeSmuME {
 
 impl Drop for DeSmuME {
     fn drop(&mut self) {
-        unsafe {
-            // Freeing DeSmuME will prevent it from ever be used again. So at the moment we keep
-            // it around, in case it is needed again later. You can use free_desmume() to manually
-            // free it.
-            //desmume_free();
-            ALREADY_INITIALIZED = false;
-        }
+        // Freeing DeSmuME will prevent it from ever be used again. So at the moment we keep
+        // it around, in case it is needed again later. You can use free_desmume() to manually
+        // free it.
+        lock_instance().alive = false;
     }
 }
 
@@ -274,10 +261,34 @@ impl Drop for DeSmuME {
 /// This function could be removed in a future update and DeSmuME freeing could be automated,
 /// but only when freeing DeSmuME doesn't prevent it from being initialized again.
 ///
-/// # Safety
-/// This is only safe to call ONCE after an instance of DeSmuME was created and is only safe to call
-/// if no instance exists anymore. After calling this function no instance of DeSmuME can be created
-/// anymore (doing so leads to undefined behaviour).
-pub unsafe fn free_desmume() {
-    desmume_free()
+/// Returns [`DeSmuMEError::AlreadyInit`] if an instance still exists. Afterwards
+/// [`DeSmuME::init`] returns [`DeSmuMEError::Freed`]. Calling this again does nothing.
+pub fn free_desmume() -> Result<(), DeSmuMEError> {
+    let mut instance = lock_instance();
+    if instance.alive {
+        return Err(DeSmuMEError::AlreadyInit)
+    }
+    if instance.native == NativeState::Ready {
+        unsafe { desmume_free() }
+    }
+    instance.native = NativeState::Freed;
+    Ok(())
+}
+
+struct InstanceState {
+    /// Whether a [`DeSmuME`] instance exists.
+    alive: bool,
+    native: NativeState
+}
+
+#[derive(PartialEq, Eq)]
+enum NativeState {
+    Uninit,
+    Ready,
+    Freed
+}
+
+fn lock_instance() -> MutexGuard<'static, InstanceState> {
+    // The state is always consistent, even if a thread panicked while holding the lock.
+    INSTANCE.lock().unwrap_or_else(|e| e.into_inner())
 }
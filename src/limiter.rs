@@ -0,0 +1,111 @@
+//! Frame pacing, see [`SpeedMode`].
+
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::DeSmuMEError;
+
+/// Frame rate of the DS.
+pub const FRAME_RATE: f64 = 59.8261;
+/// Slowest and fastest speed multiplier supported by [`SpeedMode::Speed`].
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 8.0;
+/// Frames used to measure the emulation speed.
+const MEASURED_FRAMES: usize = 60;
+
+/// How fast [`crate::LoadedDeSmuME::cycle`] emulates frames,
+/// see [`crate::DeSmuME::set_speed_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SpeedMode {
+    /// Emulate frames at the speed of the DS (about 59.8261 frames per second).
+    RealTime,
+    /// Emulate frames at a multiple of the speed of the DS, between [`MIN_SPEED`] and
+    /// [`MAX_SPEED`], eg. `0.5` for slow motion or `2.0` to fast-forward.
+    Speed(f32),
+    /// Emulate frames as fast as `cycle` is called.
+    #[default]
+    Unthrottled
+}
+
+impl SpeedMode {
+    /// Duration of a frame in this mode, `None` if frames are not throttled.
+    fn frame_duration(&self) -> Option<Duration> {
+        match self {
+            Self::RealTime => Some(Duration::from_secs_f64(1.0 / FRAME_RATE)),
+            Self::Speed(speed) => Some(Duration::from_secs_f64(1.0 / (FRAME_RATE * *speed as f64))),
+            Self::Unthrottled => None
+        }
+    }
+}
+
+/// Paces frames according to a [`SpeedMode`] and measures the emulation speed.
+#[derive(Default)]
+pub(crate) struct FrameLimiter {
+    mode: SpeedMode,
+    /// When the next frame is due.
+    next_frame: Option<Instant>,
+    /// End times of the last frames, oldest first.
+    frame_times: VecDeque<Instant>
+}
+
+impl FrameLimiter {
+    pub(crate) fn mode(&self) -> SpeedMode {
+        self.mode
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: SpeedMode) -> Result<(), DeSmuMEError> {
+        if let SpeedMode::Speed(speed) = mode {
+            if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+                return Err(DeSmuMEError::InvalidSpeed(speed));
+            }
+        }
+        self.mode = mode;
+        self.next_frame = None;
+        self.frame_times.clear();
+        Ok(())
+    }
+
+    /// Time left until the next frame is due.
+    pub(crate) fn remaining(&self) -> Duration {
+        match (self.mode, self.next_frame) {
+            (SpeedMode::Unthrottled, _) | (_, None) => Duration::ZERO,
+            (_, Some(next_frame)) => next_frame.saturating_duration_since(Instant::now())
+        }
+    }
+
+    /// Sleep until the next frame is due.
+    pub(crate) fn wait(&self) {
+        let remaining = self.remaining();
+        if !remaining.is_zero() {
+            thread::sleep(remaining);
+        }
+    }
+
+    /// Record that a frame was emulated and schedule the next one.
+    pub(crate) fn frame_done(&mut self) {
+        let now = Instant::now();
+        if self.frame_times.len() == MEASURED_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now);
+
+        self.next_frame = self.mode.frame_duration().map(|duration| {
+            match self.next_frame {
+                // Catch up after a slow frame, but not after falling behind by more than a frame,
+                // eg. while the game was paused.
+                Some(next_frame) if next_frame + duration >= now => next_frame + duration,
+                _ => now + duration
+            }
+        });
+    }
+
+    /// Frames emulated per second, measured over the last frames.
+    pub(crate) fn fps(&self) -> f64 {
+        match (self.frame_times.front(), self.frame_times.back()) {
+            (Some(first), Some(last)) if last > first => {
+                (self.frame_times.len() - 1) as f64 / last.duration_since(*first).as_secs_f64()
+            }
+            _ => 0.0
+        }
+    }
+}
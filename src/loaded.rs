@@ -0,0 +1,593 @@
+//! The emulator with a ROM loaded, see [`LoadedDeSmuME`].
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::ptr::slice_from_raw_parts;
+use std::rc::Rc;
+use tempfile::NamedTempFile;
+use crate::ffi::*;
+use crate::frame::FrameCounter;
+use crate::hooks::{HookKind, HookRegistry};
+use crate::mem::{IndexMove, MemType};
+use crate::rewind::{FrameInput, RewindBuffer};
+use crate::rom::{HEADER_SIZE, RomHeader};
+use crate::screenshot::{self, ScreenSelection};
+use crate::video::VideoRecorder;
+use crate::{
+    Compositor, DeSmuME, DeSmuMEBackup, DeSmuMEError, DeSmuMEMemory, DeSmuMEMovie, DeSmuMESavestate, Frame,
+    FrameCounters, HookHandle, OpenError, RewindConfig, SavestateMetadata, RAM_HEADER_ADDR, SCREEN_HEIGHT, SCREEN_HEIGHT_BOTH,
+    SCREEN_PIXEL_SIZE_BOTH, SCREEN_WIDTH
+};
+
+/// The DeSmuME emulator with a ROM loaded, returned by [`DeSmuME::open`] and the other `open`
+/// functions.
+///
+/// Everything that needs a game to be loaded, like emulating frames, accessing memory,
+/// savestates and movies, is only available here. The other functions of [`DeSmuME`] can be
+/// used through [`Deref`], except for [`DeSmuME::with_savetype`], which only applies to opening.
+pub struct LoadedDeSmuME {
+    emu: DeSmuME,
+    memory: DeSmuMEMemory,
+    movie: DeSmuMEMovie,
+    savestate: DeSmuMESavestate,
+    backup: DeSmuMEBackup,
+    /// Temporary file backing a ROM opened from memory. DeSmuME may stream the ROM from disk,
+    /// so this must be kept around until another ROM is opened.
+    rom_file: Option<NamedTempFile>,
+    rewind: Option<RewindBuffer>,
+    hooks: Rc<RefCell<HookRegistry>>,
+    counter: Rc<FrameCounter>,
+    /// The volume to restore, while in headless mode.
+    headless_volume: Option<u8>,
+    render_next_frame: bool,
+    /// Whether the screens were rendered during the last emulated frame.
+    frame_rendered: bool,
+    recorder: Option<VideoRecorder>
+}
+
+impl LoadedDeSmuME {
+    /// Open a ROM with `open` and return the emulator with the ROM loaded,
+    /// or give the emulator back if that fails.
+    pub(crate) fn open_with<F>(emu: DeSmuME, open: F) -> Result<Self, OpenError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), DeSmuMEError>
+    {
+        let counter = Rc::new(FrameCounter::default());
+        let mut loaded = Self {
+            emu,
+            memory: DeSmuMEMemory(PhantomData),
+            movie: DeSmuMEMovie::new(counter.clone()),
+            savestate: DeSmuMESavestate::new(counter.clone()),
+            backup: DeSmuMEBackup(PhantomData),
+            rom_file: None,
+            rewind: None,
+            hooks: Default::default(),
+            counter,
+            headless_volume: None,
+            render_next_frame: false,
+            frame_rendered: false,
+            recorder: None
+        };
+        match open(&mut loaded) {
+            Ok(()) => Ok(loaded),
+            Err(error) => Err(OpenError { emu: loaded.emu, error })
+        }
+    }
+
+    pub fn memory(&self) -> &DeSmuMEMemory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut DeSmuMEMemory {
+        &mut self.memory
+    }
+
+    pub fn movie(&self) -> &DeSmuMEMovie {
+        &self.movie
+    }
+
+    pub fn movie_mut(&mut self) -> &mut DeSmuMEMovie {
+        &mut self.movie
+    }
+
+    pub fn savestate(&self) -> &DeSmuMESavestate {
+        &self.savestate
+    }
+
+    pub fn savestate_mut(&mut self) -> &mut DeSmuMESavestate {
+        &mut self.savestate
+    }
+
+    pub fn backup(&self) -> &DeSmuMEBackup {
+        &self.backup
+    }
+
+    pub fn backup_mut(&mut self) -> &mut DeSmuMEBackup {
+        &mut self.backup
+    }
+
+    /// Open another ROM by file name, replacing the loaded one. See [`DeSmuME::open`].
+    pub fn open(&mut self, file_name: &str, auto_resume: bool) -> Result<(), DeSmuMEError> {
+        self.open_raw(file_name, auto_resume)?;
+        self.rom_file = None;
+        Ok(())
+    }
+
+    /// Open a ROM by path.
+    ///
+    /// Unlike [`LoadedDeSmuME::open`] the ROM header is validated first, so that a damaged or truncated
+    /// image is reported with a specific error. The header checksum is not checked, see
+    /// [`RomHeader::parse`].
+    ///
+    /// See [`DeSmuME::open`] for the meaning of `auto_resume`.
+    pub fn open_path(&mut self, path: &Path, auto_resume: bool) -> Result<(), DeSmuMEError> {
+        let mut file = File::open(path)?;
+        let rom_size = file.metadata()?.len() as usize;
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        (&mut file).take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+        RomHeader::parse(&header)?.validate_size(rom_size)?;
+
+        let file_name = path.to_str().ok_or_else(|| DeSmuMEError::InvalidPath(path.to_path_buf()))?;
+        self.open(file_name, auto_resume)
+    }
+
+    /// Open a ROM by path and replace its backup memory (SRAM) with `backup` before booting it,
+    /// eg. to load a raw `.sav` file.
+    ///
+    /// See [`DeSmuME::open`] for the meaning of `auto_resume`.
+    pub fn open_with_backup(&mut self, path: &Path, backup: &[u8], auto_resume: bool) -> Result<(), DeSmuMEError> {
+        self.open_path(path, false)?;
+        self.backup.write(backup)?;
+        if auto_resume {
+            self.resume(false);
+        }
+        Ok(())
+    }
+
+    /// Open a ROM from an in-memory ROM image.
+    ///
+    /// The ROM header is validated before the image is booted. Since DeSmuME can only load ROMs
+    /// from disk, the image is written to a temporary file, that is deleted once another ROM is
+    /// opened or the emulator is dropped.
+    ///
+    /// See [`DeSmuME::open`] for the meaning of `auto_resume`.
+    pub fn open_bytes(&mut self, rom: &[u8], auto_resume: bool) -> Result<(), DeSmuMEError> {
+        RomHeader::parse(rom)?.validate_size(rom.len())?;
+
+        let mut file = tempfile::Builder::new()
+            .prefix("rs_desmume")
+            .suffix(".nds")
+            .tempfile()?;
+        file.write_all(rom)?;
+        file.flush()?;
+
+        let file_name = file.path().to_str().ok_or_else(|| DeSmuMEError::InvalidPath(file.path().to_path_buf()))?;
+        self.open_raw(file_name, auto_resume)?;
+        self.rom_file = Some(file);
+        Ok(())
+    }
+
+    fn open_raw(&mut self, file_name: &str, auto_resume: bool) -> Result<(), DeSmuMEError> {
+        unsafe {
+            if desmume_open(CString::new(file_name)?.as_ptr()) < 0 {
+                return Err(DeSmuMEError::FailedOpen);
+            }
+        }
+        self.savestate.rom_opened(Path::new(file_name));
+        self.counter.reset();
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+        if auto_resume {
+            self.resume(false);
+        }
+        Ok(())
+    }
+
+    /// Pause the emulator.
+    pub fn pause(&mut self) {
+        unsafe { desmume_pause() }
+    }
+
+    /// Resume / unpause the emulator. This will reset the keypad (release all keys),
+    /// except if `keep_keypad` is true.
+    pub fn resume(&mut self, keep_keypad: bool) {
+        if keep_keypad {
+            self.emu.input.keypad_update(0);
+        }
+        unsafe { desmume_resume(); }
+    }
+
+    /// Resets the emulator / restarts the current game.
+    pub fn reset(&mut self) {
+        unsafe { desmume_reset(); }
+        self.counter.reset();
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+    }
+
+    /// Returns `true`, if a game is loaded and the emulator is running (not paused).
+    pub fn is_running(&self) -> bool {
+        unsafe { desmume_running() > 0 }
+    }
+
+    /// Tell the emulator to skip the next frame.
+    pub fn skip_next_frame(&mut self) {
+        unsafe { desmume_skip_next_frame() }
+    }
+
+    /// Enable or disable the headless mode. While enabled, frames are emulated without
+    /// rendering the screens (see [`LoadedDeSmuME::skip_next_frame`]) and the volume is
+    /// set to 0, so that frames are emulated as fast as possible. Combine this with
+    /// [`crate::SpeedMode::Unthrottled`] for batch runs.
+    ///
+    /// The display buffer keeps the last rendered frame, use
+    /// [`LoadedDeSmuME::render_next_frame`] to render single frames, eg. to take screenshots.
+    /// The volume from before the headless mode was enabled is restored when disabling it.
+    pub fn set_headless(&mut self, headless: bool) {
+        match (headless, self.headless_volume) {
+            (true, None) => {
+                self.headless_volume = Some(self.emu.volume_get());
+                self.emu.volume_set(0);
+            }
+            (false, Some(volume)) => {
+                self.headless_volume = None;
+                self.emu.volume_set(volume);
+            }
+            _ => {}
+        }
+        self.render_next_frame = false;
+    }
+
+    /// Returns `true`, if the headless mode is enabled, see [`LoadedDeSmuME::set_headless`].
+    pub fn is_headless(&self) -> bool {
+        self.headless_volume.is_some()
+    }
+
+    /// Returns `true`, if the screens were rendered during the last emulated frame, so that the
+    /// display buffer shows it. See [`LoadedDeSmuME::set_headless`].
+    pub fn is_frame_rendered(&self) -> bool {
+        self.frame_rendered
+    }
+
+    /// Render the screens during the next frame, even though the headless mode is enabled.
+    pub fn render_next_frame(&mut self) {
+        self.render_next_frame = true;
+    }
+
+    /// Cycle one game cycle / frame.
+    ///
+    /// Hooks registered with [`LoadedDeSmuME::on_frame_start`] and [`LoadedDeSmuME::on_frame_end`] are
+    /// run before and after the frame is emulated.
+    ///
+    /// Unless the emulator is unthrottled, this waits until the frame is due,
+    /// see [`DeSmuME::set_speed_mode`].
+    pub fn cycle(&mut self) {
+        self.emu.limiter.wait();
+        self.emulate_frame(|emu| {
+            if let Some(rewind) = &mut emu.rewind {
+                rewind.before_frame(&mut emu.savestate, &emu.emu.input);
+            }
+            !emu.skip_rendering()
+        });
+        if let Some(recorder) = &self.recorder {
+            let image = recorder.compositor().compose(&self.frame());
+            self.recorder.as_mut().unwrap().write_frame(image);
+        }
+        self.emu.limiter.frame_done();
+    }
+
+    /// Emulate one frame, with the frame hooks and frame counters. This is shared by
+    /// [`LoadedDeSmuME::cycle`] and the frames replayed when rewinding. `before` runs after the
+    /// frame start hooks, right before the frame is emulated, and returns whether to render it.
+    fn emulate_frame<F: FnOnce(&mut Self) -> bool>(&mut self, before: F) {
+        let hooks = self.hooks.clone();
+        HookRegistry::run(&hooks, HookKind::FrameStart, self);
+        self.frame_rendered = before(self);
+        if !self.frame_rendered {
+            unsafe { desmume_skip_next_frame() }
+        }
+        self.counter.begin_frame();
+        unsafe { desmume_cycle(self.emu.input.joystick_was_init as c_bool) }
+        self.counter.end_frame();
+        HookRegistry::run(&hooks, HookKind::FrameEnd, self);
+    }
+
+    /// Start recording every frame emulated by [`LoadedDeSmuME::cycle`] to a video file at
+    /// `path`, with the screens arranged by `compositor`. The format is determined by the file
+    /// extension, see [`VideoFormat`]. Frames are stored uncompressed.
+    ///
+    /// Audio is not recorded, the library has no access to the audio output of DeSmuME.
+    /// Frames that are not rendered (see [`LoadedDeSmuME::set_headless`]) repeat the last
+    /// rendered frame.
+    ///
+    /// If a video is already being recorded, that recording is stopped first.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, compositor: Compositor) -> Result<(), DeSmuMEError> {
+        if self.recorder.is_some() {
+            self.stop_recording()?;
+        }
+        self.recorder = Some(VideoRecorder::create(path.as_ref(), compositor)?);
+        Ok(())
+    }
+
+    /// Stop recording and finish the video file. Returns the number of recorded frames, or the
+    /// first error that occurred while recording. Fails with [`DeSmuMEError::NotRecording`]
+    /// if no video is being recorded.
+    ///
+    /// The recording is also finished when the emulator is dropped.
+    pub fn stop_recording(&mut self) -> Result<u32, DeSmuMEError> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Err(DeSmuMEError::NotRecording)
+        }
+    }
+
+    /// Returns `true`, if a video is being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Whether the screens should not be rendered during the next frame.
+    fn skip_rendering(&mut self) -> bool {
+        self.headless_volume.is_some() && !mem::take(&mut self.render_next_frame)
+    }
+
+    /// Register a closure that is run at the start of every [`LoadedDeSmuME::cycle`], before the frame
+    /// is emulated. This can eg. be used to set input for the frame.
+    ///
+    /// Hooks run in the order they were registered. The hook is removed when the returned
+    /// handle is dropped.
+    pub fn on_frame_start<F: FnMut(&mut LoadedDeSmuME) + 'static>(&mut self, hook: F) -> HookHandle {
+        HookRegistry::add(&self.hooks, HookKind::FrameStart, Box::new(hook))
+    }
+
+    /// Register a closure that is run at the end of every [`LoadedDeSmuME::cycle`], after the frame
+    /// was emulated. This can eg. be used to read memory or the display buffer.
+    ///
+    /// Hooks run in the order they were registered. The hook is removed when the returned
+    /// handle is dropped.
+    pub fn on_frame_end<F: FnMut(&mut LoadedDeSmuME) + 'static>(&mut self, hook: F) -> HookHandle {
+        HookRegistry::add(&self.hooks, HookKind::FrameEnd, Box::new(hook))
+    }
+
+    /// Returns the number of frames emulated since the ROM was opened or the emulator was reset.
+    ///
+    /// The counter is stored in the metadata of savestate files and restored when loading them,
+    /// see [`DeSmuMESavestate::save_to_vec_with_counters`] for in-memory savestates.
+    pub fn frame_count(&self) -> u64 {
+        self.counter.get().frames
+    }
+
+    /// Returns the number of lag frames since the ROM was opened or the emulator was reset.
+    /// A frame is a lag frame, if the game did not read the keypad during it.
+    ///
+    /// The counter is stored with savestates like [`LoadedDeSmuME::frame_count`].
+    pub fn lag_frame_count(&self) -> u64 {
+        self.counter.get().lag_frames
+    }
+
+    /// Returns both frame counters.
+    pub fn frame_counters(&self) -> FrameCounters {
+        self.counter.get()
+    }
+
+    /// Returns `true`, if the last emulated frame was a lag frame.
+    pub fn is_lag_frame(&self) -> bool {
+        self.counter.is_lag_frame()
+    }
+
+    /// Emulate `frames` frames. Returns the number of frames emulated.
+    pub fn run_frames(&mut self, frames: u32) -> u32 {
+        for _ in 0..frames {
+            self.cycle();
+        }
+        frames
+    }
+
+    /// Emulate frames until `predicate` returns true, checking it before the first and after
+    /// every emulated frame. Returns the number of frames emulated, or [`DeSmuMEError::Timeout`]
+    /// if the predicate was still false after `max_frames` frames.
+    pub fn run_until<F: FnMut(&LoadedDeSmuME) -> bool>(&mut self, mut predicate: F, max_frames: u32) -> Result<u32, DeSmuMEError> {
+        if predicate(self) {
+            return Ok(0);
+        }
+        for frame in 1..=max_frames {
+            self.cycle();
+            if predicate(self) {
+                return Ok(frame);
+            }
+        }
+        Err(DeSmuMEError::Timeout(max_frames))
+    }
+
+    /// Emulate frames until the value at `addr` equals `value`. See [`LoadedDeSmuME::run_until`].
+    ///
+    /// The type of `value` determines how memory is read, eg. pass a `u16` to compare two bytes.
+    pub fn run_until_memory<T: MemType + PartialEq>(&mut self, addr: u32, value: T, max_frames: u32) -> Result<u32, DeSmuMEError> {
+        self.run_until(|emu| T::read_from(emu.memory(), addr) == value, max_frames)
+    }
+
+    /// Enable the rewind buffer. While enabled, a snapshot of the emulator is taken every
+    /// `config.interval` frames during [`LoadedDeSmuME::cycle`] and the input of every frame is recorded.
+    ///
+    /// Snapshots are stored as deltas against each other to keep memory use down. The buffer is
+    /// cleared when a ROM is opened or the emulator is reset. Loading a savestate does not clear
+    /// it, rewinding will return to the timeline before the savestate was loaded.
+    ///
+    /// If rewinding is already enabled, the buffer is replaced.
+    pub fn enable_rewind(&mut self, config: RewindConfig) {
+        self.rewind = Some(RewindBuffer::new(config));
+    }
+
+    /// Disable the rewind buffer and drop all of its snapshots.
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Returns the number of frames that can currently be rewound.
+    pub fn rewind_available(&self) -> u32 {
+        self.rewind.as_ref().map(|r| r.available() as u32).unwrap_or(0)
+    }
+
+    /// Rewind the emulator by `frames` frames. The nearest earlier snapshot is restored and the
+    /// recorded input is replayed up to the requested frame.
+    ///
+    /// If the buffer does not reach back far enough, the oldest snapshot is restored.
+    /// Returns the number of frames that were actually rewound.
+    ///
+    /// The replayed frames run the frame hooks like [`LoadedDeSmuME::cycle`], but are not
+    /// throttled, recorded or rendered (except for the last one).
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, DeSmuMEError> {
+        let rewind = self.rewind.as_mut().ok_or(DeSmuMEError::RewindNotEnabled)?;
+        let (frames, replay) = rewind.restore(frames, &mut self.savestate)?;
+        let current_input = FrameInput::capture(&self.emu.input);
+        let last = replay.len();
+        for (i, frame_input) in replay.into_iter().enumerate() {
+            self.emulate_frame(|emu| {
+                frame_input.apply(&mut emu.emu.input);
+                i + 1 == last
+            });
+        }
+        current_input.apply(&mut self.emu.input);
+        Ok(frames)
+    }
+
+    /// Return the display buffer in the internal format.
+    /// You probably want to use display_buffer_as_rgbx instead.
+    pub fn display_buffer(&self) -> &[u16] {
+        unsafe {
+            &*slice_from_raw_parts(desmume_draw_raw(), SCREEN_PIXEL_SIZE_BOTH)
+        }
+    }
+
+    /// Return a copy of the display buffer, with views of the top and bottom screen and
+    /// conversions to common color formats.
+    pub fn frame(&self) -> Frame {
+        Frame::new(self.display_buffer().to_vec(), SCREEN_WIDTH, SCREEN_HEIGHT).unwrap()
+    }
+
+    /// Save a screenshot of the selected screens to `path`. The image format is determined by
+    /// the file extension: `.ppm`, `.bmp` or, with the `png` feature, `.png`.
+    /// The image holds the exact pixels of the display buffer.
+    pub fn screenshot<P: AsRef<Path>>(&self, path: P, screens: ScreenSelection) -> Result<(), DeSmuMEError> {
+        self.screenshot_with(path, &screens.into())
+    }
+
+    /// Save a screenshot of the screens arranged by `compositor`, see [`LoadedDeSmuME::screenshot`].
+    pub fn screenshot_with<P: AsRef<Path>>(&self, path: P, compositor: &Compositor) -> Result<(), DeSmuMEError> {
+        screenshot::save(&self.frame(), path.as_ref(), compositor)
+    }
+
+    /// Fill in the display buffer as RGBX color values,
+    /// see the screen size constants for how many pixels make up lines.
+    ///
+    /// Fails with [`DeSmuMEError::BufferTooSmall`] if the slice holds less than
+    /// `SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4` bytes.
+    pub fn display_buffer_as_rgbx_into(&self, buffer: &mut [u8]) -> Result<(), DeSmuMEError> {
+        let required = SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4;
+        if buffer.len() < required {
+            return Err(DeSmuMEError::BufferTooSmall { required, actual: buffer.len() });
+        }
+        unsafe { desmume_draw_raw_as_rgbx(buffer.as_mut_ptr()) }
+        Ok(())
+    }
+
+    /// Return the display buffer as RGBX color values,
+    /// see the screen size constants for how many pixels make up lines.
+    pub fn display_buffer_as_rgbx(&self) -> Vec<u8> {
+        let mut buff = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4];
+        self.display_buffer_as_rgbx_into(&mut buff).unwrap();
+        buff
+    }
+
+    /// Collect metadata about the current state of the emulator, to be stored alongside a
+    /// savestate (see [`DeSmuMESavestate::save_with_metadata`]).
+    ///
+    /// If `capture_screens` is true, the current contents of both screens are included.
+    pub fn savestate_metadata(&self, label: &str, capture_screens: bool) -> SavestateMetadata {
+        let header = RomHeader::parse(&self.memory.u8().index_move(RAM_HEADER_ADDR..RAM_HEADER_ADDR + HEADER_SIZE as u32)).ok();
+        SavestateMetadata {
+            title: header.as_ref().map(|h| h.title()).unwrap_or_default(),
+            game_code: header.as_ref().map(|h| h.game_code()).unwrap_or_default(),
+            frame: self.counter.get().frames,
+            lag_frames: self.counter.get().lag_frames,
+            label: label.to_owned(),
+            screens: capture_screens.then(|| self.display_buffer_as_rgbx())
+        }
+    }
+}
+
+impl Deref for LoadedDeSmuME {
+    type Target = DeSmuME;
+
+    fn deref(&self) -> &DeSmuME {
+        &self.emu
+    }
+}
+
+impl DerefMut for LoadedDeSmuME {
+    fn deref_mut(&mut self) -> &mut DeSmuME {
+        &mut self.emu
+    }
+}
+
+/// The emulator owned by an emulator thread or worker process, with or without a ROM loaded.
+pub(crate) enum EmulatorState {
+    Idle(DeSmuME),
+    Loaded(Box<LoadedDeSmuME>),
+    /// While a ROM is being opened. The state stays like this if opening panicked, as the
+    /// emulator is dropped then.
+    Opening
+}
+
+impl EmulatorState {
+    /// Returns the emulator, or [`DeSmuMEError::EmulatorLost`] if opening a ROM panicked.
+    pub(crate) fn emu(&mut self) -> Result<&mut DeSmuME, DeSmuMEError> {
+        match self {
+            Self::Idle(emu) => Ok(emu),
+            Self::Loaded(emu) => Ok(emu),
+            Self::Opening => Err(DeSmuMEError::EmulatorLost)
+        }
+    }
+
+    /// Returns the emulator, or [`DeSmuMEError::NoRomLoaded`] if no ROM is loaded.
+    pub(crate) fn loaded(&mut self) -> Result<&mut LoadedDeSmuME, DeSmuMEError> {
+        match self {
+            Self::Loaded(emu) => Ok(emu.as_mut()),
+            _ => Err(DeSmuMEError::NoRomLoaded)
+        }
+    }
+
+    /// Open a ROM with `open`, whether a ROM is already loaded or not.
+    pub(crate) fn open<F>(&mut self, open: F) -> Result<(), DeSmuMEError>
+    where
+        F: FnOnce(&mut LoadedDeSmuME) -> Result<(), DeSmuMEError>
+    {
+        match mem::replace(self, Self::Opening) {
+            Self::Idle(emu) => match LoadedDeSmuME::open_with(emu, open) {
+                Ok(emu) => {
+                    *self = Self::Loaded(Box::new(emu));
+                    Ok(())
+                }
+                Err(e) => {
+                    let (emu, error) = e.into_parts();
+                    *self = Self::Idle(emu);
+                    Err(error)
+                }
+            },
+            Self::Loaded(mut emu) => {
+                let result = open(&mut emu);
+                *self = Self::Loaded(emu);
+                result
+            }
+            Self::Opening => Err(DeSmuMEError::EmulatorLost)
+        }
+    }
+}
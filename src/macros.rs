@@ -4,7 +4,7 @@ macro_rules! impl_read_access (
             unsafe fn read_range(&self, start: u32, end: u32) -> Vec<$integer_type> {
                 let size_of = std::mem::size_of::<$integer_type>();
                 assert_eq!(0, (end as usize - start as usize + 1) % size_of);
-                (start..end).step_by(size_of).map(|a| $read_fn(a as c_int) as $integer_type).collect()
+                (start..=end).step_by(size_of).map(|a| $read_fn(a as c_int) as $integer_type).collect()
             }
 
             unsafe fn read(&self, addr: u32) -> $integer_type {
@@ -28,7 +28,7 @@ macro_rules! impl_write_access (
             unsafe fn read_range(&self, start: u32, end: u32) -> Vec<$integer_type> {
                 let size_of = std::mem::size_of::<$integer_type>();
                 assert_eq!(0, (end as usize - start as usize + 1) % size_of);
-                (start..end).step_by(size_of).map(|a| $read_fn(a as c_int) as $integer_type).collect()
+                (start..=end).step_by(size_of).map(|a| $read_fn(a as c_int) as $integer_type).collect()
             }
 
             unsafe fn read(&self, addr: u32) -> $integer_type {
@@ -39,7 +39,7 @@ macro_rules! impl_write_access (
                 let size_of = std::mem::size_of::<$integer_type>();
                 assert_eq!(0, (end as usize - start as usize + 1) % size_of);
                 assert_eq!((end as usize - start as usize + 1) / size_of, source.len());
-                for (addr, value) in std::iter::zip((start..end).step_by(size_of), source) {
+                for (addr, value) in std::iter::zip((start..=end).step_by(size_of), source) {
                     $write_fn(addr as c_int, *value as $as_unsigned)
                 }
             }
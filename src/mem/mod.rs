@@ -3,9 +3,10 @@ mod index;
 
 use std::marker::PhantomData;
 use crate::ffi::*;
+use crate::frame;
 pub use crate::ffi::MemoryCbFnc;
 pub use crate::mem::index::{IndexSet, IndexMove};
-pub use crate::mem::read::{MemIndexWrapper, TypedMemoryReader, TypedMemoryWriter};
+pub use crate::mem::read::{MemIndexWrapper, MemType, TypedMemoryReader, TypedMemoryWriter};
 
 pub enum Processor {
     Arm9, Arm7
@@ -29,6 +30,29 @@ pub enum Register {
 }
 
 impl Register {
+    /// The index of general purpose registers (`R0` - `R15` and their aliases).
+    pub(crate) fn index(&self) -> Option<usize> {
+        Some(match self {
+            Register::R0 => 0,
+            Register::R1 => 1,
+            Register::R2 => 2,
+            Register::R3 => 3,
+            Register::R4 => 4,
+            Register::R5 => 5,
+            Register::R6 => 6,
+            Register::R7 => 7,
+            Register::R8 => 8,
+            Register::R9 => 9,
+            Register::R10 => 10,
+            Register::R11 => 11,
+            Register::R12 => 12,
+            Register::R13 | Register::SP => 13,
+            Register::R14 | Register::LR => 14,
+            Register::R15 | Register::PC => 15,
+            Register::CPSR | Register::SPSR => return None
+        })
+    }
+
     fn get_name(&self) -> &str {
         match self {
             Register::R0 => "r0",
@@ -235,8 +259,16 @@ impl DeSmuMEMemory {
     ///
     /// `size` is the maximum size that will be watched. If you set this to 4 for example,
     ///  a range of (address, address + 3) will be monitored.
+    ///
+    /// Lag frame detection uses a read callback on the KEYINPUT register (`0x04000130`).
+    /// A callback for this address is run by the lag frame detection instead of replacing it,
+    /// for reads of the whole register (`size` is ignored).
     pub fn register_read(&mut self, address: u32, size: u16, callback: MemoryCbFnc) {
-        unsafe { desmume_memory_register_read(address as c_int, size as c_int, callback) }
+        if address == frame::KEYINPUT {
+            frame::set_keypad_read_callback(callback);
+        } else {
+            unsafe { desmume_memory_register_read(address as c_int, size as c_int, callback) }
+        }
     }
 
     /// Add a memory callback for when the memory at the specified address was read.
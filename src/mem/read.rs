@@ -4,13 +4,16 @@ use std::ops::{Deref, DerefMut, Range, RangeFrom, RangeFull, RangeInclusive, Ran
 use crate::mem::{DeSmuMEMemory, IndexMove, IndexSet};
 
 /// Numeric data types that can be read from / written into NDS memory.
-pub trait MemType: Sized + Copy {}
-impl MemType for u8 { }
-impl MemType for u16 { }
-impl MemType for u32 { }
-impl MemType for i8 { }
-impl MemType for i16 { }
-impl MemType for i32 { }
+pub trait MemType: Sized + Copy {
+    /// Read a single value of this type from memory.
+    fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self;
+}
+impl MemType for u8 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.u8().index_move(addr) } }
+impl MemType for u16 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.u16().index_move(addr) } }
+impl MemType for u32 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.u32().index_move(addr) } }
+impl MemType for i8 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.i8().index_move(addr) } }
+impl MemType for i16 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.i16().index_move(addr) } }
+impl MemType for i32 { fn read_from(mem: &DeSmuMEMemory, addr: u32) -> Self { mem.i32().index_move(addr) } }
 
 const START_OF_MEMORY: u32 = 0;
 const END_OF_MEMORY: u32 = 0xFFFFFFFF;  // todo: is this true?
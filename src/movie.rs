@@ -1,13 +1,20 @@
 use std::ffi::{CStr, CString};
-use std::marker::PhantomData;
+use std::rc::Rc;
 use crate::DeSmuMEError;
 use crate::ffi::*;
+use crate::frame::FrameCounter;
 pub use crate::ffi::SimpleDate;
 
 /// Record and play movies.
-pub struct DeSmuMEMovie(pub(crate) PhantomData<()>);
+pub struct DeSmuMEMovie {
+    counter: Rc<FrameCounter>
+}
 
 impl DeSmuMEMovie {
+    pub(crate) fn new(counter: Rc<FrameCounter>) -> Self {
+        Self { counter }
+    }
+
     /// Load a movie file from a file and play it back.
     pub fn play(&mut self, file_name: &str) -> Result<(), DeSmuMEError> {
         unsafe {
@@ -67,6 +74,22 @@ impl DeSmuMEMovie {
         unsafe { desmume_movie_is_finished() > 0 }
     }
 
+    /// Returns the number of frames emulated since the ROM was opened or the emulator was reset.
+    /// See [`crate::LoadedDeSmuME::frame_count`].
+    pub fn frame_count(&self) -> u64 {
+        self.counter.get().frames
+    }
+
+    /// Returns the number of lag frames. See [`crate::LoadedDeSmuME::lag_frame_count`].
+    pub fn lag_frame_count(&self) -> u64 {
+        self.counter.get().lag_frames
+    }
+
+    /// Returns `true`, if the last emulated frame was a lag frame.
+    pub fn is_lag_frame(&self) -> bool {
+        self.counter.is_lag_frame()
+    }
+
     /// Returns an error if no movie is active.
     pub fn get_length(&self) -> Result<u32, DeSmuMEError> {
         if self.is_active() {
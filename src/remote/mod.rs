@@ -0,0 +1,338 @@
+//! Run emulators in worker processes, see [`RemoteDeSmuME`].
+
+mod protocol;
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{self, Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::handle::{read_bytes, write_bytes};
+use crate::loaded::EmulatorState;
+use crate::remote::protocol::*;
+use crate::{DeSmuME, DeSmuMEError};
+
+/// Environment variable that tells a process started by [`RemoteDeSmuME`] to run as worker.
+const WORKER_ENV: &str = "RS_DESMUME_WORKER";
+/// Written by the worker before the first message, so that output of the process before
+/// [`worker_main`] was called can be skipped.
+const WORKER_MAGIC: &[u8] = b"RS_DESMUME_WORKER\n";
+/// How long the worker has to start and initialize DeSmuME.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the worker has to exit after it was told to shut down, before it is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An emulator running in a worker process.
+///
+/// DeSmuME can only be initialized once per process. Every `RemoteDeSmuME` runs its own
+/// instance in a child process, so several games can be emulated in parallel and a crashing
+/// emulator does not take down the controlling process. The methods mirror those of [`DeSmuME`]
+/// and are sent to the worker over its stdin and stdout.
+///
+/// The worker is started from the same binary (or from the command passed to
+/// [`RemoteDeSmuME::spawn_command`]), which must call [`worker_main`] at the start of `main`.
+/// The worker is shut down when the `RemoteDeSmuME` is dropped, and killed if it does not exit
+/// in time.
+pub struct RemoteDeSmuME {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl RemoteDeSmuME {
+    /// Start a worker process from the current executable.
+    pub fn spawn() -> Result<Self, DeSmuMEError> {
+        Self::spawn_command(Command::new(env::current_exe()?))
+    }
+
+    /// Start a worker process with the given command. This can eg. be used to pass arguments
+    /// to test binaries, so that only a test calling [`worker_main`] is run:
+    ///
+    /// ```rs
+    /// #[test]
+    /// fn desmume_worker() {
+    ///     rs_desmume::remote::worker_main();
+    /// }
+    ///
+    /// let mut command = Command::new(env::current_exe()?);
+    /// command.args(["desmume_worker", "--exact"]);
+    /// let emu = RemoteDeSmuME::spawn_command(command)?;
+    /// ```
+    pub fn spawn_command(mut command: Command) -> Result<Self, DeSmuMEError> {
+        command.env(WORKER_ENV, "1").stdin(Stdio::piped()).stdout(Stdio::piped());
+        let mut child = command.spawn()?;
+        match connect(&mut child) {
+            Ok((input, output)) => Ok(Self { child, input, output }),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// The process ID of the worker.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Open a ROM, see [`LoadedDeSmuME::open_path`].
+    pub fn open(&mut self, path: &Path, auto_resume: bool) -> Result<(), DeSmuMEError> {
+        let path = path.to_str().ok_or_else(|| DeSmuMEError::InvalidPath(path.to_path_buf()))?.to_owned();
+        self.call_unit(Request::OpenPath { path, auto_resume })
+    }
+
+    /// Open a ROM from memory, see [`LoadedDeSmuME::open_bytes`].
+    pub fn open_bytes(&mut self, rom: &[u8], auto_resume: bool) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::OpenBytes { rom: rom.to_vec(), auto_resume })
+    }
+
+    pub fn pause(&mut self) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::Pause)
+    }
+
+    /// See [`LoadedDeSmuME::resume`].
+    pub fn resume(&mut self, keep_keypad: bool) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::Resume { keep_keypad })
+    }
+
+    pub fn reset(&mut self) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::Reset)
+    }
+
+    pub fn is_running(&mut self) -> Result<bool, DeSmuMEError> {
+        self.call_with(Request::IsRunning, |r| r.bool())
+    }
+
+    pub fn cycle(&mut self) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::Cycle)
+    }
+
+    /// Emulate `frames` frames in the worker, without a round trip for every frame.
+    pub fn run_frames(&mut self, frames: u32) -> Result<u32, DeSmuMEError> {
+        self.call_with(Request::RunFrames(frames), |r| r.u32())
+    }
+
+    pub fn frame_count(&mut self) -> Result<u64, DeSmuMEError> {
+        self.call_with(Request::FrameCount, |r| r.u64())
+    }
+
+    pub fn lag_frame_count(&mut self) -> Result<u64, DeSmuMEError> {
+        self.call_with(Request::LagFrameCount, |r| r.u64())
+    }
+
+    /// See [`crate::DeSmuMEInput::keypad_update`].
+    pub fn keypad_update(&mut self, keys: u16) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::KeypadUpdate(keys))
+    }
+
+    /// See [`crate::DeSmuMEInput::keypad_get`].
+    pub fn keypad_get(&mut self) -> Result<u16, DeSmuMEError> {
+        self.call_with(Request::KeypadGet, |r| r.u16())
+    }
+
+    pub fn touch_set_pos(&mut self, x: u16, y: u16) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::TouchSetPos(x, y))
+    }
+
+    pub fn touch_release(&mut self) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::TouchRelease)
+    }
+
+    /// Read `len` bytes of memory starting at `addr`, see [`crate::EmulatorHandle::read_memory`].
+    pub fn read_memory(&mut self, addr: u32, len: u32) -> Result<Vec<u8>, DeSmuMEError> {
+        self.call_with(Request::ReadMemory { addr, len }, |r| r.bytes())
+    }
+
+    /// Write `data` to the memory starting at `addr`, see [`crate::EmulatorHandle::write_memory`].
+    pub fn write_memory(&mut self, addr: u32, data: &[u8]) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::WriteMemory { addr, data: data.to_vec() })
+    }
+
+    /// See [`crate::DeSmuMESavestate::save_to_vec`].
+    pub fn save_state(&mut self) -> Result<Vec<u8>, DeSmuMEError> {
+        self.call_with(Request::SaveState, |r| r.bytes())
+    }
+
+    /// See [`crate::DeSmuMESavestate::load_from_slice`].
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::LoadState(data.to_vec()))
+    }
+
+    /// See [`LoadedDeSmuME::display_buffer_as_rgbx`].
+    pub fn display_buffer_as_rgbx(&mut self) -> Result<Vec<u8>, DeSmuMEError> {
+        self.call_with(Request::DisplayBuffer, |r| r.bytes())
+    }
+
+    pub fn volume_get(&mut self) -> Result<u8, DeSmuMEError> {
+        self.call_with(Request::VolumeGet, |r| r.u8())
+    }
+
+    pub fn volume_set(&mut self, volume: u8) -> Result<(), DeSmuMEError> {
+        self.call_unit(Request::VolumeSet(volume))
+    }
+
+    fn call_unit(&mut self, request: Request) -> Result<(), DeSmuMEError> {
+        self.call_with(request, |_| Ok(()))
+    }
+
+    fn call_with<T>(&mut self, request: Request, read: impl FnOnce(&mut Reader) -> Result<T, DeSmuMEError>) -> Result<T, DeSmuMEError> {
+        write_message(&mut self.input, &request.encode())?;
+        let response = decode_response(read_message(&mut self.output)?)?;
+        let mut reader = Reader(&response);
+        let value = read(&mut reader)?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+impl Drop for RemoteDeSmuME {
+    fn drop(&mut self) {
+        // The response is not awaited, a worker that does not exit in time is killed.
+        let _ = write_message(&mut self.input, &Request::Shutdown.encode());
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Run as worker process of a [`RemoteDeSmuME`], if this process was started as one.
+/// Otherwise this returns immediately.
+///
+/// Call this at the start of `main`. When running as worker, the process exits once the
+/// `RemoteDeSmuME` is dropped.
+pub fn worker_main() {
+    if env::var_os(WORKER_ENV).is_none() {
+        return;
+    }
+    let code = match run_worker() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("rs_desmume worker: {}", e);
+            1
+        }
+    };
+    process::exit(code)
+}
+
+fn run_worker() -> Result<(), DeSmuMEError> {
+    let mut input = io::stdin().lock();
+    let mut output = take_stdout()?;
+    output.write_all(WORKER_MAGIC)?;
+    let mut state = match DeSmuME::init() {
+        Ok(emu) => {
+            write_message(&mut output, &encode_response(Ok(vec![])))?;
+            EmulatorState::Idle(emu)
+        }
+        Err(e) => return write_message(&mut output, &encode_response(Err(e))),
+    };
+    loop {
+        let Ok(message) = read_message(&mut input) else {
+            // The controlling process is gone.
+            return Ok(());
+        };
+        let request = Request::decode(&message);
+        let shutdown = matches!(request, Ok(Request::Shutdown));
+        let response = request.and_then(|request| handle(&mut state, request));
+        write_message(&mut output, &encode_response(response))?;
+        if shutdown {
+            return Ok(());
+        }
+    }
+}
+
+/// Take over stdout for the protocol. Everything else written to stdout from now on, eg. log
+/// output of DeSmuME, goes to stderr instead.
+#[cfg(unix)]
+fn take_stdout() -> Result<std::fs::File, DeSmuMEError> {
+    use std::os::unix::io::FromRawFd;
+    io::stdout().flush()?;
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let file = std::fs::File::from_raw_fd(fd);
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(file)
+    }
+}
+
+/// Output written to stdout by DeSmuME is not redirected on this platform and must be disabled.
+#[cfg(not(unix))]
+fn take_stdout() -> Result<io::Stdout, DeSmuMEError> {
+    Ok(io::stdout())
+}
+
+fn handle(state: &mut EmulatorState, request: Request) -> Result<Vec<u8>, DeSmuMEError> {
+    let mut out = Writer::default();
+    match request {
+        Request::OpenPath { path, auto_resume } => state.open(|emu| emu.open_path(Path::new(&path), auto_resume))?,
+        Request::OpenBytes { rom, auto_resume } => state.open(|emu| emu.open_bytes(&rom, auto_resume))?,
+        Request::Pause => state.loaded()?.pause(),
+        Request::Resume { keep_keypad } => state.loaded()?.resume(keep_keypad),
+        Request::Reset => state.loaded()?.reset(),
+        Request::IsRunning => { out.bool(state.loaded().map(|emu| emu.is_running()).unwrap_or(false)); }
+        Request::Cycle => state.loaded()?.cycle(),
+        Request::RunFrames(frames) => { out.u32(state.loaded()?.run_frames(frames)); }
+        Request::FrameCount => { out.u64(state.loaded()?.frame_count()); }
+        Request::LagFrameCount => { out.u64(state.loaded()?.lag_frame_count()); }
+        Request::KeypadUpdate(keys) => state.emu()?.input_mut().keypad_update(keys),
+        Request::KeypadGet => { out.u16(state.emu()?.input().keypad_get()); }
+        Request::TouchSetPos(x, y) => state.emu()?.input_mut().touch_set_pos(x, y),
+        Request::TouchRelease => state.emu()?.input_mut().touch_release(),
+        Request::ReadMemory { addr, len } => { out.bytes(&read_bytes(state.loaded()?, addr, len)?); }
+        Request::WriteMemory { addr, data } => write_bytes(state.loaded()?, addr, &data)?,
+        Request::SaveState => { out.bytes(&state.loaded()?.savestate_mut().save_to_vec()?); }
+        Request::LoadState(data) => state.loaded()?.savestate_mut().load_from_slice(&data)?,
+        Request::DisplayBuffer => { out.bytes(&state.loaded()?.display_buffer_as_rgbx()); }
+        Request::VolumeGet => { out.u8(state.emu()?.volume_get()); }
+        Request::VolumeSet(volume) => state.emu()?.volume_set(volume),
+        Request::Shutdown => {}
+    }
+    Ok(out.0)
+}
+
+/// Wait for the worker to start and initialize DeSmuME.
+fn connect(child: &mut Child) -> Result<(ChildStdin, BufReader<ChildStdout>), DeSmuMEError> {
+    let (Some(input), Some(output)) = (child.stdin.take(), child.stdout.take()) else {
+        return Err(DeSmuMEError::Remote("The pipes to the worker process are not available.".to_owned()));
+    };
+    // Reading from the pipe can not time out, so it is done on another thread. If the worker
+    // is killed, the pipe is closed and the thread ends.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = BufReader::new(output);
+        let _ = sender.send(handshake(&mut output).map(|()| output));
+    });
+    match receiver.recv_timeout(CONNECT_TIMEOUT) {
+        Ok(output) => Ok((input, output?)),
+        Err(_) => Err(DeSmuMEError::Remote("The worker process did not start in time.".to_owned())),
+    }
+}
+
+/// Skip the output of the worker before it called [`worker_main`] (eg. that of the test
+/// harness) and read the result of initializing DeSmuME.
+fn handshake(output: &mut impl BufRead) -> Result<(), DeSmuMEError> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if output.read_until(b'\n', &mut line)? == 0 {
+            return Err(DeSmuMEError::Remote(
+                "The worker process exited before starting, make sure it calls worker_main.".to_owned()
+            ));
+        }
+        if line.ends_with(WORKER_MAGIC) {
+            break;
+        }
+    }
+    decode_response(read_message(output)?)?;
+    Ok(())
+}
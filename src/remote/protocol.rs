@@ -0,0 +1,377 @@
+//! The protocol between [`super::RemoteDeSmuME`] and its worker process.
+//!
+//! Every message is a little endian `u32` length followed by the payload. Requests start with
+//! an opcode, responses with a status byte (0 = ok, 1 = error) followed by the result or the
+//! error. Errors keep their [`DeSmuMEError`] variant, so callers can match on them.
+
+use std::ffi::CString;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use crate::DeSmuMEError;
+
+/// Messages larger than this are treated as a protocol error.
+const MAX_MESSAGE_SIZE: usize = 256 * 1024 * 1024;
+/// I/O error kinds that are kept when an I/O error without OS error code is sent. Others are
+/// sent as [`ErrorKind::Other`].
+const IO_ERROR_KINDS: [ErrorKind; 8] = [
+    ErrorKind::Other,
+    ErrorKind::NotFound,
+    ErrorKind::PermissionDenied,
+    ErrorKind::AlreadyExists,
+    ErrorKind::InvalidInput,
+    ErrorKind::InvalidData,
+    ErrorKind::UnexpectedEof,
+    ErrorKind::TimedOut,
+];
+
+pub(crate) enum Request {
+    OpenPath { path: String, auto_resume: bool },
+    OpenBytes { rom: Vec<u8>, auto_resume: bool },
+    Pause,
+    Resume { keep_keypad: bool },
+    Reset,
+    IsRunning,
+    Cycle,
+    RunFrames(u32),
+    FrameCount,
+    LagFrameCount,
+    KeypadUpdate(u16),
+    KeypadGet,
+    TouchSetPos(u16, u16),
+    TouchRelease,
+    ReadMemory { addr: u32, len: u32 },
+    WriteMemory { addr: u32, data: Vec<u8> },
+    SaveState,
+    LoadState(Vec<u8>),
+    DisplayBuffer,
+    VolumeGet,
+    VolumeSet(u8),
+    Shutdown,
+}
+
+impl Request {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Writer::default();
+        match self {
+            Self::OpenPath { path, auto_resume } => out.u8(0).bytes(path.as_bytes()).bool(*auto_resume),
+            Self::OpenBytes { rom, auto_resume } => out.u8(1).bytes(rom).bool(*auto_resume),
+            Self::Pause => out.u8(2),
+            Self::Resume { keep_keypad } => out.u8(3).bool(*keep_keypad),
+            Self::Reset => out.u8(4),
+            Self::IsRunning => out.u8(5),
+            Self::Cycle => out.u8(6),
+            Self::RunFrames(frames) => out.u8(7).u32(*frames),
+            Self::FrameCount => out.u8(8),
+            Self::LagFrameCount => out.u8(9),
+            Self::KeypadUpdate(keys) => out.u8(10).u16(*keys),
+            Self::KeypadGet => out.u8(11),
+            Self::TouchSetPos(x, y) => out.u8(12).u16(*x).u16(*y),
+            Self::TouchRelease => out.u8(13),
+            Self::ReadMemory { addr, len } => out.u8(14).u32(*addr).u32(*len),
+            Self::WriteMemory { addr, data } => out.u8(15).u32(*addr).bytes(data),
+            Self::SaveState => out.u8(16),
+            Self::LoadState(data) => out.u8(17).bytes(data),
+            Self::DisplayBuffer => out.u8(18),
+            Self::VolumeGet => out.u8(19),
+            Self::VolumeSet(volume) => out.u8(20).u8(*volume),
+            Self::Shutdown => out.u8(21),
+        };
+        out.0
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Result<Self, DeSmuMEError> {
+        let mut reader = Reader(data);
+        let request = match reader.u8()? {
+            0 => Self::OpenPath { path: reader.string()?, auto_resume: reader.bool()? },
+            1 => Self::OpenBytes { rom: reader.bytes()?, auto_resume: reader.bool()? },
+            2 => Self::Pause,
+            3 => Self::Resume { keep_keypad: reader.bool()? },
+            4 => Self::Reset,
+            5 => Self::IsRunning,
+            6 => Self::Cycle,
+            7 => Self::RunFrames(reader.u32()?),
+            8 => Self::FrameCount,
+            9 => Self::LagFrameCount,
+            10 => Self::KeypadUpdate(reader.u16()?),
+            11 => Self::KeypadGet,
+            12 => Self::TouchSetPos(reader.u16()?, reader.u16()?),
+            13 => Self::TouchRelease,
+            14 => Self::ReadMemory { addr: reader.u32()?, len: reader.u32()? },
+            15 => Self::WriteMemory { addr: reader.u32()?, data: reader.bytes()? },
+            16 => Self::SaveState,
+            17 => Self::LoadState(reader.bytes()?),
+            18 => Self::DisplayBuffer,
+            19 => Self::VolumeGet,
+            20 => Self::VolumeSet(reader.u8()?),
+            21 => Self::Shutdown,
+            opcode => return Err(invalid(&format!("Unknown opcode {}.", opcode))),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+pub(crate) fn encode_response(response: Result<Vec<u8>, DeSmuMEError>) -> Vec<u8> {
+    match response {
+        Ok(mut data) => {
+            data.insert(0, 0);
+            data
+        }
+        Err(e) => {
+            let mut out = Writer::default();
+            encode_error(out.u8(1), &e);
+            out.0
+        }
+    }
+}
+
+pub(crate) fn decode_response(mut data: Vec<u8>) -> Result<Vec<u8>, DeSmuMEError> {
+    match data.first() {
+        Some(0) => {
+            data.remove(0);
+            Ok(data)
+        }
+        Some(1) => {
+            let mut reader = Reader(&data[1..]);
+            let error = decode_error(&mut reader)?;
+            reader.finish()?;
+            Err(error)
+        }
+        _ => Err(invalid("Invalid response.")),
+    }
+}
+
+fn encode_error(out: &mut Writer, error: &DeSmuMEError) {
+    use DeSmuMEError::*;
+    match error {
+        AlreadyInit => out.u8(0),
+        Freed => out.u8(1),
+        FailedInit => out.u8(2),
+        FailedOpen => out.u8(3),
+        RomTooSmall(size) => out.u8(4).usize(*size),
+        RomBadHeaderChecksum { expected, actual } => out.u8(5).u16(*expected).u16(*actual),
+        RomTruncated { expected, actual } => out.u8(6).usize(*expected).usize(*actual),
+        InvalidPath(path) => out.u8(7).string(&path.to_string_lossy()),
+        FailedInitWindow => out.u8(8),
+        LoadSavestateFailed(reason) => out.u8(9).string(reason),
+        SaveSavestateFailed(reason) => out.u8(10).string(reason),
+        InvalidSavestate(reason) => out.u8(11).string(reason),
+        InvalidAddress(addr) => out.u8(12).u32(*addr),
+        MemoryRequestTooLarge { len, max } => out.u8(13).usize(*len).u32(*max),
+        InvalidSavestateSlot(slot) => out.u8(14).u8(*slot),
+        SavestateSlotEmpty(slot) => out.u8(15).u8(*slot),
+        SavestateSlotPathUnknown(slot) => out.u8(16).u8(*slot),
+        InvalidSavestateMetadata(reason) => out.u8(17).string(reason),
+        InvalidSavestateDate(date) => out.u8(18).string(date),
+        BackupFailed(reason) => out.u8(19).string(reason),
+        InvalidBackup(reason) => out.u8(20).string(reason),
+        Timeout(frames) => out.u8(21).u32(*frames),
+        InvalidSpeed(speed) => out.u8(22).u32(speed.to_bits()),
+        BufferTooSmall { required, actual } => out.u8(23).usize(*required).usize(*actual),
+        UnsupportedImageFormat(path) => out.u8(24).string(&path.to_string_lossy()),
+        InvalidCompositorScale => out.u8(25),
+        UnsupportedVideoFormat(path) => out.u8(26).string(&path.to_string_lossy()),
+        VideoTooLarge => out.u8(27),
+        NotRecording => out.u8(28),
+        RewindNotEnabled => out.u8(29),
+        RewindEmpty => out.u8(30),
+        EmulatorThreadStopped => out.u8(31),
+        NoRomLoaded => out.u8(32),
+        EmulatorLost => out.u8(33),
+        Remote(reason) => out.u8(34).string(reason),
+        RemoteDisconnected => out.u8(35),
+        MoviePlayError(reason) => out.u8(36).string(reason),
+        NoMovieActive => out.u8(37),
+        JoystickNotInit => out.u8(38),
+        FailedInitJoystick => out.u8(39),
+        NulError(e) => out.u8(40).bytes(&e.clone().into_vec()),
+        Io(e) => match e.raw_os_error() {
+            Some(code) => out.u8(41).bool(true).u32(code as u32),
+            None => {
+                let kind = IO_ERROR_KINDS.iter().position(|kind| *kind == e.kind()).unwrap_or(0);
+                out.u8(41).bool(false).u8(kind as u8).string(&e.to_string())
+            }
+        },
+    };
+}
+
+fn decode_error(reader: &mut Reader) -> Result<DeSmuMEError, DeSmuMEError> {
+    use DeSmuMEError::*;
+    Ok(match reader.u8()? {
+        0 => AlreadyInit,
+        1 => Freed,
+        2 => FailedInit,
+        3 => FailedOpen,
+        4 => RomTooSmall(reader.usize()?),
+        5 => RomBadHeaderChecksum { expected: reader.u16()?, actual: reader.u16()? },
+        6 => RomTruncated { expected: reader.usize()?, actual: reader.usize()? },
+        7 => InvalidPath(PathBuf::from(reader.string()?)),
+        8 => FailedInitWindow,
+        9 => LoadSavestateFailed(reader.string()?),
+        10 => SaveSavestateFailed(reader.string()?),
+        11 => InvalidSavestate(reader.string()?),
+        12 => InvalidAddress(reader.u32()?),
+        13 => MemoryRequestTooLarge { len: reader.usize()?, max: reader.u32()? },
+        14 => InvalidSavestateSlot(reader.u8()?),
+        15 => SavestateSlotEmpty(reader.u8()?),
+        16 => SavestateSlotPathUnknown(reader.u8()?),
+        17 => InvalidSavestateMetadata(reader.string()?),
+        18 => InvalidSavestateDate(reader.string()?),
+        19 => BackupFailed(reader.string()?),
+        20 => InvalidBackup(reader.string()?),
+        21 => Timeout(reader.u32()?),
+        22 => InvalidSpeed(f32::from_bits(reader.u32()?)),
+        23 => BufferTooSmall { required: reader.usize()?, actual: reader.usize()? },
+        24 => UnsupportedImageFormat(PathBuf::from(reader.string()?)),
+        25 => InvalidCompositorScale,
+        26 => UnsupportedVideoFormat(PathBuf::from(reader.string()?)),
+        27 => VideoTooLarge,
+        28 => NotRecording,
+        29 => RewindNotEnabled,
+        30 => RewindEmpty,
+        31 => EmulatorThreadStopped,
+        32 => NoRomLoaded,
+        33 => EmulatorLost,
+        34 => Remote(reader.string()?),
+        35 => RemoteDisconnected,
+        36 => MoviePlayError(reader.string()?),
+        37 => NoMovieActive,
+        38 => JoystickNotInit,
+        39 => FailedInitJoystick,
+        40 => match CString::new(reader.bytes()?) {
+            Err(e) => NulError(e),
+            Ok(_) => return Err(invalid("String without nul byte in NulError.")),
+        },
+        41 => Io(if reader.bool()? {
+            io::Error::from_raw_os_error(reader.u32()? as i32)
+        } else {
+            let kind = IO_ERROR_KINDS.get(reader.u8()? as usize).copied().unwrap_or(ErrorKind::Other);
+            io::Error::new(kind, reader.string()?)
+        }),
+        code => return Err(invalid(&format!("Unknown error code {}.", code))),
+    })
+}
+
+pub(crate) fn write_message(stream: &mut impl Write, data: &[u8]) -> Result<(), DeSmuMEError> {
+    stream.write_all(&(data.len() as u32).to_le_bytes()).map_err(disconnected)?;
+    stream.write_all(data).map_err(disconnected)?;
+    stream.flush().map_err(disconnected)
+}
+
+pub(crate) fn read_message(stream: &mut impl Read) -> Result<Vec<u8>, DeSmuMEError> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).map_err(disconnected)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(invalid(&format!("Message of {} bytes is too large.", len)));
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data).map_err(disconnected)?;
+    Ok(data)
+}
+
+#[derive(Default)]
+pub(crate) struct Writer(pub(crate) Vec<u8>);
+
+impl Writer {
+    pub(crate) fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) -> &mut Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    fn usize(&mut self, value: usize) -> &mut Self {
+        self.u64(value as u64)
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.0.extend(value);
+        self
+    }
+
+    fn string(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeSmuMEError> {
+        if self.0.len() < len {
+            return Err(invalid("Unexpected end of message."));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DeSmuMEError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, DeSmuMEError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DeSmuMEError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DeSmuMEError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, DeSmuMEError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, DeSmuMEError> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("Value out of range."))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<Vec<u8>, DeSmuMEError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, DeSmuMEError> {
+        String::from_utf8(self.bytes()?).map_err(|_| invalid("Invalid string."))
+    }
+
+    /// Fail if there is unread data left.
+    pub(crate) fn finish(&self) -> Result<(), DeSmuMEError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("Unexpected data at the end of the message."))
+        }
+    }
+}
+
+fn disconnected(_: std::io::Error) -> DeSmuMEError {
+    DeSmuMEError::RemoteDisconnected
+}
+
+fn invalid(reason: &str) -> DeSmuMEError {
+    DeSmuMEError::Remote(format!("Protocol error: {}", reason))
+}
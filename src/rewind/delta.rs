@@ -0,0 +1,136 @@
+//! The delta encoding used to store rewind snapshots compactly.
+//!
+//! The XOR of two buffers is stored as a sequence of `(zero run length, literal length,
+//! literals)` entries with LEB128 encoded lengths. Consecutive snapshots mostly share their
+//! content, so this is much smaller than storing each snapshot in full.
+
+/// Encode the difference between two buffers, so that `older` can be restored from `newer`
+/// with [`apply`]. The buffers may have different lengths.
+pub(crate) fn encode(newer: &[u8], older: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| older[i] ^ newer.get(i).copied().unwrap_or(0);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < older.len() {
+        let run_start = i;
+        while i < older.len() && xor(i) == 0 {
+            i += 1;
+        }
+        if i == older.len() {
+            break;
+        }
+        let literal_start = i;
+        // Short zero runs inside of a literal are cheaper to store than a new entry.
+        while i < older.len() && (xor(i) != 0 || (i + 4 < older.len() && (i..i + 4).any(|j| xor(j) != 0))) {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - run_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(xor));
+    }
+    out
+}
+
+/// Restore the older buffer of length `len` from `newer` and a patch created by [`encode`].
+///
+/// Returns `None`, if the patch is malformed or does not fit into `len` bytes.
+pub(crate) fn apply(newer: &[u8], len: usize, patch: &[u8]) -> Option<Vec<u8>> {
+    let mut out = newer.to_vec();
+    out.resize(len, 0);
+    let mut pos = 0usize;
+    let mut cursor = 0;
+    while cursor < patch.len() {
+        pos = pos.checked_add(read_varint(patch, &mut cursor)?)?;
+        let literal_len = read_varint(patch, &mut cursor)?;
+        let literals = patch.get(cursor..cursor.checked_add(literal_len)?)?;
+        let target = out.get_mut(pos..pos.checked_add(literal_len)?)?;
+        target.iter_mut().zip(literals).for_each(|(b, l)| *b ^= l);
+        pos += literal_len;
+        cursor += literal_len;
+    }
+    Some(out)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], cursor: &mut usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*cursor)?;
+        *cursor += 1;
+        if shift >= usize::BITS {
+            return None;
+        }
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(newer: &[u8], older: &[u8]) -> Vec<u8> {
+        let patch = encode(newer, older);
+        let restored = apply(newer, older.len(), &patch).unwrap();
+        assert_eq!(older, &restored[..]);
+        patch
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let older: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut newer = older.clone();
+        newer[10] ^= 1;
+        newer[500..520].fill(0xAA);
+        let patch = roundtrip(&newer, &older);
+        assert!(patch.len() < 40);
+
+        assert!(roundtrip(&older, &older).is_empty());
+        roundtrip(&[], &older);
+        roundtrip(&older, &[]);
+    }
+
+    #[test]
+    fn test_delta_unequal_lengths() {
+        let older: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        // The newer buffer is shorter, the older one is restored from the zero padded buffer.
+        roundtrip(&older[..100], &older);
+        // The newer buffer is longer, the restored buffer is truncated.
+        let mut longer = older.clone();
+        longer.extend([1; 50]);
+        roundtrip(&longer, &older);
+    }
+
+    #[test]
+    fn test_delta_long_runs() {
+        // Zero runs and literals longer than 127 bytes need multi-byte lengths.
+        let older: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let mut newer = older.clone();
+        newer[200..400].iter_mut().for_each(|b| *b = !*b);
+        newer[70_000..90_000].iter_mut().for_each(|b| *b ^= 0x55);
+        roundtrip(&newer, &older);
+    }
+
+    #[test]
+    fn test_delta_malformed_patch() {
+        let older = [1; 300];
+        let patch = encode(&[0; 300], &older);
+        assert_eq!(apply(&[0; 300], 200, &patch), None);
+        assert_eq!(apply(&[0; 300], 300, &patch[..patch.len() - 1]), None);
+        assert_eq!(apply(&[0; 300], 300, &[0x80; 20]), None);
+    }
+}
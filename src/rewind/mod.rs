@@ -0,0 +1,179 @@
+//! Rewinding the emulator, see [`crate::LoadedDeSmuME::enable_rewind`].
+
+mod delta;
+
+use std::collections::VecDeque;
+use crate::{DeSmuMEError, DeSmuMEInput, DeSmuMESavestate, FrameCounters};
+
+/// Configuration of the rewind buffer, see [`crate::LoadedDeSmuME::enable_rewind`].
+#[derive(Debug, Clone, Copy)]
+pub struct RewindConfig {
+    /// Number of frames between two snapshots. Rewinding restores the nearest earlier snapshot
+    /// and replays the recorded input from there, so smaller intervals make rewinding faster
+    /// but snapshotting more costly.
+    pub interval: u32,
+    /// Maximum number of snapshots kept. When the buffer is full, the oldest snapshot is dropped.
+    pub capacity: usize,
+}
+
+impl Default for RewindConfig {
+    /// A snapshot every second (60 frames), for up to one minute.
+    fn default() -> Self {
+        Self { interval: 60, capacity: 60 }
+    }
+}
+
+/// Input state of a single frame, needed to replay frames after restoring a snapshot.
+#[derive(Clone, Copy)]
+pub(crate) struct FrameInput {
+    keypad: u16,
+    touch_pos: Option<(u16, u16)>,
+}
+
+impl FrameInput {
+    pub(crate) fn capture(input: &DeSmuMEInput) -> Self {
+        Self { keypad: input.keypad_get(), touch_pos: input.touch_get_pos() }
+    }
+
+    pub(crate) fn apply(&self, input: &mut DeSmuMEInput) {
+        input.keypad_update(self.keypad);
+        match self.touch_pos {
+            Some((x, y)) => input.touch_set_pos(x, y),
+            None => input.touch_release(),
+        }
+    }
+}
+
+/// The newest snapshot, stored in full.
+struct Snapshot {
+    frame: u64,
+    state: Vec<u8>,
+    counters: FrameCounters,
+}
+
+/// An older snapshot, stored as a delta against the next newer snapshot.
+struct Delta {
+    frame: u64,
+    len: usize,
+    patch: Vec<u8>,
+    counters: FrameCounters,
+}
+
+/// A bounded ring of savestate snapshots taken while the emulator is cycled.
+pub(crate) struct RewindBuffer {
+    config: RewindConfig,
+    /// Number of frames cycled since the buffer was created or cleared.
+    frame: u64,
+    head: Option<Snapshot>,
+    /// Older snapshots, newest first.
+    history: VecDeque<Delta>,
+    /// Input of every frame starting at `inputs_start`.
+    inputs: VecDeque<FrameInput>,
+    inputs_start: u64,
+}
+
+impl RewindBuffer {
+    pub(crate) fn new(config: RewindConfig) -> Self {
+        Self {
+            config: RewindConfig { interval: config.interval.max(1), capacity: config.capacity.max(1) },
+            frame: 0,
+            head: None,
+            history: VecDeque::new(),
+            inputs: VecDeque::new(),
+            inputs_start: 0,
+        }
+    }
+
+    /// Drop all snapshots. Needed whenever the emulated timeline is replaced (eg. a ROM is opened).
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// The number of frames that can currently be rewound.
+    pub(crate) fn available(&self) -> u64 {
+        match self.history.back() {
+            Some(oldest) => self.frame - oldest.frame,
+            None => self.head.as_ref().map(|head| self.frame - head.frame).unwrap_or(0),
+        }
+    }
+
+    /// Called before every emulated frame. Takes a snapshot if needed and records the input.
+    pub(crate) fn before_frame(&mut self, savestate: &mut DeSmuMESavestate, input: &DeSmuMEInput) {
+        let due = match &self.head {
+            Some(head) => self.frame - head.frame >= self.config.interval as u64,
+            None => true,
+        };
+        if due {
+            // A failed snapshot only means there is one less point to rewind to.
+            if let Ok((state, counters)) = savestate.save_to_vec_with_counters() {
+                self.push(state, counters);
+            }
+        }
+        if self.head.is_some() {
+            self.inputs.push_back(FrameInput::capture(input));
+        }
+        self.frame += 1;
+    }
+
+    fn push(&mut self, state: Vec<u8>, counters: FrameCounters) {
+        let frame = self.frame;
+        if let Some(previous) = self.head.replace(Snapshot { frame, state, counters }) {
+            let head = self.head.as_ref().unwrap();
+            self.history.push_front(Delta {
+                frame: previous.frame,
+                len: previous.state.len(),
+                patch: delta::encode(&head.state, &previous.state),
+                counters: previous.counters,
+            });
+        } else {
+            self.inputs.clear();
+            self.inputs_start = frame;
+        }
+        while self.history.len() + 1 > self.config.capacity {
+            self.history.pop_back();
+        }
+        let oldest = self.history.back().map(|d| d.frame).unwrap_or(frame);
+        while self.inputs_start < oldest {
+            self.inputs.pop_front();
+            self.inputs_start += 1;
+        }
+    }
+
+    /// Restore the snapshot nearest to `frames` frames ago (or the oldest snapshot, if the buffer
+    /// does not reach back this far). Returns the number of frames rewound and the input of the
+    /// frames that must be replayed from the restored snapshot to reach the target frame.
+    pub(crate) fn restore(&mut self, frames: u32, savestate: &mut DeSmuMESavestate) -> Result<(u32, Vec<FrameInput>), DeSmuMEError> {
+        let head = self.head.as_ref().ok_or(DeSmuMEError::RewindEmpty)?;
+        let frames = (frames as u64).min(self.available());
+        let target = self.frame - frames;
+
+        // Walk back from the newest snapshot until one at or before the target is found.
+        let mut state = head.state.clone();
+        let mut snapshot_frame = head.frame;
+        let mut counters = head.counters;
+        let mut restored = 0;
+        for delta in &self.history {
+            if snapshot_frame <= target {
+                break;
+            }
+            state = delta::apply(&state, delta.len, &delta.patch).ok_or_else(|| {
+                DeSmuMEError::InvalidSavestate("A rewind snapshot is corrupted.".to_owned())
+            })?;
+            snapshot_frame = delta.frame;
+            counters = delta.counters;
+            restored += 1;
+        }
+        savestate.load_from_slice_with_counters(&state, counters)?;
+
+        let first = (snapshot_frame - self.inputs_start) as usize;
+        let last = (target - self.inputs_start) as usize;
+        let replay = self.inputs.range(first..last).copied().collect();
+
+        // Everything newer than the restored snapshot belongs to the abandoned timeline.
+        self.history.drain(..restored);
+        self.head = Some(Snapshot { frame: snapshot_frame, state, counters });
+        self.inputs.truncate(last);
+        self.frame = target;
+        Ok((frames as u32, replay))
+    }
+}
@@ -0,0 +1,115 @@
+use std::ops::Range;
+use crate::DeSmuMEError;
+
+/// Size of the part of the NDS cartridge header that is validated before booting a ROM.
+pub const HEADER_SIZE: usize = 0x200;
+
+const TITLE: Range<usize> = 0x000..0x00C;
+const GAME_CODE: Range<usize> = 0x00C..0x010;
+const MAKER_CODE: Range<usize> = 0x010..0x012;
+const ARM9_ROM_OFFSET: usize = 0x020;
+const ARM9_SIZE: usize = 0x02C;
+const ARM7_ROM_OFFSET: usize = 0x030;
+const ARM7_SIZE: usize = 0x03C;
+const HEADER_CHECKSUM: usize = 0x15E;
+
+/// The header of a Nintendo DS ROM image.
+///
+/// Used to validate ROM images before they are handed to DeSmuME, since the emulator itself only
+/// reports a generic failure (or happily boots garbage).
+#[derive(Debug, Clone)]
+pub struct RomHeader {
+    raw: Vec<u8>,
+}
+
+impl RomHeader {
+    /// Parse the header at the start of `data`.
+    /// `data` may be the entire ROM image or just its first [`HEADER_SIZE`] bytes.
+    ///
+    /// The header checksum is not checked, since DeSmuME boots ROMs with a bad checksum (eg.
+    /// homebrew or patched games). Use [`RomHeader::verify_checksum`] to check it.
+    pub fn parse(data: &[u8]) -> Result<Self, DeSmuMEError> {
+        if data.len() < HEADER_SIZE {
+            return Err(DeSmuMEError::RomTooSmall(data.len()));
+        }
+        Ok(Self { raw: data[..HEADER_SIZE].to_vec() })
+    }
+
+    /// Check the CRC-16 checksum of the header.
+    pub fn verify_checksum(&self) -> Result<(), DeSmuMEError> {
+        let expected = read_u16(&self.raw, HEADER_CHECKSUM);
+        let actual = crc16(&self.raw[..HEADER_CHECKSUM]);
+        if expected != actual {
+            return Err(DeSmuMEError::RomBadHeaderChecksum { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Check that an image of `rom_size` bytes contains everything the header references.
+    pub fn validate_size(&self, rom_size: usize) -> Result<(), DeSmuMEError> {
+        let expected = self.required_size();
+        if rom_size < expected {
+            return Err(DeSmuMEError::RomTruncated { expected, actual: rom_size });
+        }
+        Ok(())
+    }
+
+    /// The minimum size of the image, so that both the ARM9 and ARM7 binaries are contained in it.
+    /// This is `usize::MAX` if a binary ends beyond the address space.
+    pub fn required_size(&self) -> usize {
+        let end = |offset, size| {
+            (read_u32(&self.raw, offset) as usize).checked_add(read_u32(&self.raw, size) as usize)
+        };
+        match (end(ARM9_ROM_OFFSET, ARM9_SIZE), end(ARM7_ROM_OFFSET, ARM7_SIZE)) {
+            (Some(arm9_end), Some(arm7_end)) => arm9_end.max(arm7_end).max(HEADER_SIZE),
+            _ => usize::MAX
+        }
+    }
+
+    /// The game title (up to 12 ASCII characters).
+    pub fn title(&self) -> String {
+        ascii(&self.raw[TITLE])
+    }
+
+    /// The four character game code (eg. `C2SE`).
+    pub fn game_code(&self) -> String {
+        ascii(&self.raw[GAME_CODE])
+    }
+
+    /// The two character maker code (eg. `01` for Nintendo).
+    pub fn maker_code(&self) -> String {
+        ascii(&self.raw[MAKER_CODE])
+    }
+
+    /// The raw bytes of the header.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// CRC-16 (MODBUS variant) as used by the NDS header checksums.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
@@ -0,0 +1,530 @@
+//! A pure Rust reader and writer for DeSmuME's savestate (`.dst` / `.ds0`-`.ds9`) files.
+//!
+//! A savestate is a small header followed by a (possibly zlib compressed) list of chunks.
+//! Each chunk has an ID and a size. Most chunks are lists of named fields (eg. the `WRAM` field
+//! in the memory chunk holds the main RAM), some are written by custom code in DeSmuME and
+//! usually start with a version number. Those are kept as raw bytes.
+//!
+//! Savestates can also be modified and written back, see [`SavestateFile::to_bytes`].
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use flate2::read::ZlibDecoder;
+use crate::{DeSmuMEError, SaveType};
+use crate::mem::{Processor, Register};
+
+const MAGIC: &[u8; 16] = b"DeSmuME SState\0\0";
+const MAGIC_LEN: usize = 15;
+const HEADER_SIZE: usize = 32;
+const UNCOMPRESSED: u32 = 0xFFFFFFFF;
+const END_OF_CHUNKS: u32 = 0xFFFFFFFF;
+/// Offset of the backup memory buffer in the extra MMU chunk: The chunk version, followed by
+/// the backup device's version, write enable flag, command, address size, address counter and state.
+const BACKUP_DATA_OFFSET: usize = 28;
+
+/// The known chunks of a savestate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChunkKind {
+    /// ARM9 CPU registers.
+    Arm9,
+    /// ARM7 CPU registers.
+    Arm7,
+    /// ARM9 system control coprocessor.
+    Cp15,
+    /// Main RAM, TCMs, VRAM, palettes and OAM.
+    Memory,
+    /// General console state (timers, interrupts, ...).
+    Nds,
+    NdsExtra,
+    /// Memory controller state (DMA, IPC, ...).
+    Mmu,
+    /// Memory controller state, including the cartridge backup memory.
+    MmuExtra,
+    /// 2D engines.
+    Gpu,
+    /// Sound unit.
+    Spu,
+    /// Microphone.
+    Mic,
+    /// 3D engine.
+    Gfx3d,
+    Gfx3dExtra,
+    /// Movie state (frame counter, lag frames).
+    Movie,
+    MovieExtra,
+    Wifi,
+    /// Real time clock.
+    Rtc,
+    /// Information about the loaded ROM.
+    NdsInfo,
+    /// Slot-1 (cartridge) device.
+    Slot1,
+    /// Slot-2 (GBA slot) device.
+    Slot2,
+    Unknown(u32),
+}
+
+impl ChunkKind {
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            1 => Self::Arm9,
+            2 => Self::Arm7,
+            3 => Self::Cp15,
+            4 => Self::Memory,
+            5 => Self::Nds,
+            51 => Self::NdsExtra,
+            60 => Self::Mmu,
+            61 => Self::MmuExtra,
+            7 => Self::Gpu,
+            8 => Self::Spu,
+            81 => Self::Mic,
+            90 => Self::Gfx3d,
+            91 => Self::Gfx3dExtra,
+            100 => Self::Movie,
+            101 => Self::MovieExtra,
+            110 => Self::Wifi,
+            120 => Self::Rtc,
+            130 => Self::NdsInfo,
+            140 => Self::Slot1,
+            150 => Self::Slot2,
+            id => Self::Unknown(id),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Arm9 => 1,
+            Self::Arm7 => 2,
+            Self::Cp15 => 3,
+            Self::Memory => 4,
+            Self::Nds => 5,
+            Self::NdsExtra => 51,
+            Self::Mmu => 60,
+            Self::MmuExtra => 61,
+            Self::Gpu => 7,
+            Self::Spu => 8,
+            Self::Mic => 81,
+            Self::Gfx3d => 90,
+            Self::Gfx3dExtra => 91,
+            Self::Movie => 100,
+            Self::MovieExtra => 101,
+            Self::Wifi => 110,
+            Self::Rtc => 120,
+            Self::NdsInfo => 130,
+            Self::Slot1 => 140,
+            Self::Slot2 => 150,
+            Self::Unknown(id) => *id,
+        }
+    }
+
+    /// Whether DeSmuME writes this chunk as a list of named fields.
+    fn has_fields(&self) -> bool {
+        matches!(self,
+            Self::Arm9 | Self::Arm7 | Self::Memory | Self::Nds | Self::Mmu |
+            Self::Gfx3d | Self::Movie | Self::Wifi | Self::Rtc | Self::NdsInfo
+        )
+    }
+}
+
+/// A named field of a chunk. The data consists of `count` values of `size` bytes each,
+/// stored as little endian.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: [u8; 4],
+    pub size: u32,
+    pub count: u32,
+    pub data: Vec<u8>,
+}
+
+impl Field {
+    /// The name of the field, without trailing NUL bytes.
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).trim_end_matches('\0').to_owned()
+    }
+
+    fn has_name(&self, name: &str) -> bool {
+        let mut padded = [0; 4];
+        padded[..name.len().min(4)].copy_from_slice(&name.as_bytes()[..name.len().min(4)]);
+        self.name == padded
+    }
+
+    /// Returns the `index`th value of a field with 4 byte values.
+    pub fn u32(&self, index: usize) -> Option<u32> {
+        if self.size != 4 {
+            return None;
+        }
+        let bytes = self.data.get(index * 4..index * 4 + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Sets the `index`th value of a field with 4 byte values.
+    pub fn set_u32(&mut self, index: usize, value: u32) -> Result<(), DeSmuMEError> {
+        if self.size != 4 {
+            return Err(invalid(&format!("Field {} does not consist of 4 byte values.", self.name())));
+        }
+        let name = self.name();
+        let bytes = self.data.get_mut(index * 4..index * 4 + 4)
+            .ok_or_else(|| invalid(&format!("Field {} has no value {}.", name, index)))?;
+        bytes.copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Replace the data of the field, the value count is updated accordingly.
+    /// The length of `data` must be a multiple of the value size.
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<(), DeSmuMEError> {
+        if data.len().checked_rem(self.size as usize) != Some(0) {
+            return Err(invalid(&format!("The data length for field {} must be a multiple of {}.", self.name(), self.size)));
+        }
+        self.count = (data.len() / self.size as usize) as u32;
+        self.data = data;
+        Ok(())
+    }
+}
+
+/// The contents of a chunk.
+#[derive(Debug, Clone)]
+pub enum ChunkData {
+    Fields(Vec<Field>),
+    Raw(Vec<u8>),
+}
+
+/// A chunk of a savestate.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub kind: ChunkKind,
+    pub data: ChunkData,
+}
+
+impl Chunk {
+    /// The size of the chunk data in bytes (without the chunk ID and size).
+    pub fn size(&self) -> usize {
+        match &self.data {
+            ChunkData::Fields(fields) => fields.iter().map(|f| 12 + f.data.len()).sum(),
+            ChunkData::Raw(data) => data.len(),
+        }
+    }
+
+    /// The version of chunks written by custom code, which always start with their version.
+    /// `None` for chunks consisting of fields, which are not versioned.
+    pub fn version(&self) -> Option<u32> {
+        match &self.data {
+            ChunkData::Fields(_) => None,
+            ChunkData::Raw(data) => Some(u32::from_le_bytes(data.get(..4)?.try_into().unwrap())),
+        }
+    }
+
+    /// The fields of the chunk, empty for raw chunks.
+    pub fn fields(&self) -> &[Field] {
+        match &self.data {
+            ChunkData::Fields(fields) => fields,
+            ChunkData::Raw(_) => &[],
+        }
+    }
+
+    /// Returns the field with the given name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields().iter().find(|f| f.has_name(name))
+    }
+
+    /// Returns the field with the given name for modification.
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Field> {
+        match &mut self.data {
+            ChunkData::Fields(fields) => fields.iter_mut().find(|f| f.has_name(name)),
+            ChunkData::Raw(_) => None,
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend(self.kind.id().to_le_bytes());
+        out.extend((self.size() as u32).to_le_bytes());
+        match &self.data {
+            ChunkData::Fields(fields) => for field in fields {
+                out.extend(field.name);
+                out.extend(field.size.to_le_bytes());
+                out.extend(field.count.to_le_bytes());
+                out.extend(&field.data);
+            },
+            ChunkData::Raw(data) => out.extend(data),
+        }
+    }
+}
+
+/// A parsed DeSmuME savestate.
+#[derive(Debug, Clone)]
+pub struct SavestateFile {
+    /// Version of the savestate format.
+    pub version: u32,
+    /// Numeric version of the DeSmuME build that wrote the savestate.
+    pub emulator_version: u32,
+    pub chunks: Vec<Chunk>,
+}
+
+impl SavestateFile {
+    /// Parse a savestate from a file.
+    pub fn read(path: &Path) -> Result<Self, DeSmuMEError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parse a savestate, eg. as returned by [`crate::DeSmuMESavestate::save_to_vec`].
+    pub fn parse(data: &[u8]) -> Result<Self, DeSmuMEError> {
+        if data.len() < HEADER_SIZE || data[..MAGIC_LEN] != MAGIC[..MAGIC_LEN] {
+            return Err(invalid("Not a DeSmuME savestate."));
+        }
+        let version = read_u32(data, 16)?;
+        let emulator_version = read_u32(data, 20)?;
+        let len = read_u32(data, 24)? as usize;
+        let compressed_len = read_u32(data, 28)?;
+
+        let body = &data[HEADER_SIZE..];
+        let body = if compressed_len == UNCOMPRESSED {
+            body.get(..len).ok_or_else(|| invalid("The savestate is truncated."))?.to_vec()
+        } else {
+            let compressed = body.get(..compressed_len as usize).ok_or_else(|| invalid("The savestate is truncated."))?;
+            let mut out = Vec::with_capacity(len);
+            ZlibDecoder::new(compressed).read_to_end(&mut out)
+                .map_err(|e| invalid(&format!("Failed to decompress the savestate: {}", e)))?;
+            out
+        };
+
+        Ok(Self { version, emulator_version, chunks: parse_chunks(&body)? })
+    }
+
+    /// Returns the chunk of the given kind.
+    pub fn chunk(&self, kind: ChunkKind) -> Option<&Chunk> {
+        self.chunks.iter().find(|c| c.kind == kind)
+    }
+
+    /// Returns the field with the given name of the chunk of the given kind.
+    pub fn field(&self, kind: ChunkKind, name: &str) -> Option<&Field> {
+        self.chunk(kind)?.field(name)
+    }
+
+    /// The main RAM (4 MB, mapped at `0x02000000`).
+    pub fn main_ram(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "WRAM").map(|f| f.data.as_slice())
+    }
+
+    /// The ARM9 instruction TCM.
+    pub fn itcm(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "ITCM").map(|f| f.data.as_slice())
+    }
+
+    /// The ARM9 data TCM.
+    pub fn dtcm(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "DTCM").map(|f| f.data.as_slice())
+    }
+
+    /// The VRAM banks, as laid out in LCDC mode (mapped at `0x06800000`).
+    pub fn vram(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "LCDM").map(|f| f.data.as_slice())
+    }
+
+    /// The palette memory (mapped at `0x05000000`).
+    pub fn palettes(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "VMEM").map(|f| f.data.as_slice())
+    }
+
+    /// The object attribute memory (mapped at `0x07000000`).
+    pub fn oam(&self) -> Option<&[u8]> {
+        self.field(ChunkKind::Memory, "OAMS").map(|f| f.data.as_slice())
+    }
+
+    /// The contents of the cartridge backup memory (SRAM).
+    pub fn backup_data(&self) -> Option<&[u8]> {
+        let data = match &self.chunk(ChunkKind::MmuExtra)?.data {
+            ChunkData::Raw(data) => data,
+            ChunkData::Fields(_) => return None,
+        };
+        let len = read_u32(data, BACKUP_DATA_OFFSET).ok()? as usize;
+        data.get(BACKUP_DATA_OFFSET + 4..BACKUP_DATA_OFFSET + 4 + len)
+    }
+
+    /// Returns the value of a CPU register.
+    pub fn register(&self, processor: Processor, reg: Register) -> Option<u32> {
+        let (kind, field, index) = register_location(&processor, &reg);
+        self.field(kind, &field)?.u32(index)
+    }
+
+    /// Returns the chunk of the given kind for modification.
+    pub fn chunk_mut(&mut self, kind: ChunkKind) -> Option<&mut Chunk> {
+        self.chunks.iter_mut().find(|c| c.kind == kind)
+    }
+
+    /// Returns the field with the given name of the chunk of the given kind for modification.
+    pub fn field_mut(&mut self, kind: ChunkKind, name: &str) -> Option<&mut Field> {
+        self.chunk_mut(kind)?.field_mut(name)
+    }
+
+    /// The main RAM, for modification.
+    pub fn main_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.field_mut(ChunkKind::Memory, "WRAM").map(|f| f.data.as_mut_slice())
+    }
+
+    /// Set the value of a CPU register.
+    pub fn set_register(&mut self, processor: Processor, reg: Register, value: u32) -> Result<(), DeSmuMEError> {
+        let (kind, field, index) = register_location(&processor, &reg);
+        self.field_mut(kind, &field)
+            .ok_or_else(|| invalid(&format!("The savestate does not contain the field {}.", field)))?
+            .set_u32(index, value)
+    }
+
+    /// Replace the contents of the cartridge backup memory (SRAM).
+    /// DeSmuME writes the new contents to its backup file when the savestate is loaded.
+    ///
+    /// `backup` must fit into the current backup memory and is padded with `0xFF` to its size.
+    /// If the backup memory is empty, because the game did not access it yet, the size of
+    /// `backup` must be the size of one of the [`SaveType`]s.
+    pub fn set_backup_data(&mut self, backup: &[u8]) -> Result<(), DeSmuMEError> {
+        let data = match self.chunk_mut(ChunkKind::MmuExtra).map(|c| &mut c.data) {
+            Some(ChunkData::Raw(data)) => data,
+            _ => return Err(invalid("The savestate does not contain the backup memory.")),
+        };
+        let len = read_u32(data, BACKUP_DATA_OFFSET)? as usize;
+        let start = BACKUP_DATA_OFFSET + 4;
+        if data.len() < start + len {
+            return Err(invalid("The backup memory is truncated."));
+        }
+        let new_len = match len {
+            0 if SaveType::from_size(backup.len()).is_none() => return Err(DeSmuMEError::InvalidBackup(
+                format!("A backup of {} bytes does not match any save type.", backup.len())
+            )),
+            0 => backup.len(),
+            len if backup.len() > len => return Err(DeSmuMEError::InvalidBackup(
+                format!("A backup of {} bytes does not fit into the backup memory of {} bytes.", backup.len(), len)
+            )),
+            len => len,
+        };
+        let mut padded = backup.to_vec();
+        padded.resize(new_len, 0xFF);
+        data.splice(BACKUP_DATA_OFFSET..start + len, (new_len as u32).to_le_bytes().into_iter().chain(padded));
+        Ok(())
+    }
+
+    /// Read `len` bytes of memory at the NDS address `addr`, as the ARM9 sees it.
+    ///
+    /// Supported are main RAM, ITCM, palettes, VRAM (in its LCDC mapping at `0x06800000`) and OAM.
+    pub fn read_memory(&self, addr: u32, len: usize) -> Result<Vec<u8>, DeSmuMEError> {
+        let (name, offset) = memory_location(addr)?;
+        let field = self.field(ChunkKind::Memory, name)
+            .ok_or_else(|| invalid(&format!("The savestate does not contain the field {}.", name)))?;
+        field.data.get(offset..offset + len)
+            .map(|data| data.to_vec())
+            .ok_or(DeSmuMEError::InvalidAddress(addr))
+    }
+
+    /// Write `data` to the memory at the NDS address `addr`, as the ARM9 sees it.
+    ///
+    /// See [`SavestateFile::read_memory`] for the supported memory regions.
+    pub fn write_memory(&mut self, addr: u32, data: &[u8]) -> Result<(), DeSmuMEError> {
+        let (name, offset) = memory_location(addr)?;
+        let field = self.field_mut(ChunkKind::Memory, name)
+            .ok_or_else(|| invalid(&format!("The savestate does not contain the field {}.", name)))?;
+        field.data.get_mut(offset..offset + data.len())
+            .ok_or(DeSmuMEError::InvalidAddress(addr))?
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Serialize the savestate, so that it can be loaded by DeSmuME again.
+    ///
+    /// Chunk and field sizes are recalculated, so fields may have been resized. The savestate is
+    /// always written uncompressed.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for chunk in &self.chunks {
+            chunk.write_to(&mut body);
+        }
+        body.extend(END_OF_CHUNKS.to_le_bytes());
+        body.extend(0u32.to_le_bytes());
+
+        let mut out = Vec::with_capacity(HEADER_SIZE + body.len());
+        out.extend(MAGIC);
+        out.extend(self.version.to_le_bytes());
+        out.extend(self.emulator_version.to_le_bytes());
+        out.extend((body.len() as u32).to_le_bytes());
+        out.extend(UNCOMPRESSED.to_le_bytes());
+        out.extend(body);
+        out
+    }
+
+    /// Serialize the savestate to a file, see [`SavestateFile::to_bytes`].
+    pub fn write(&self, path: &Path) -> Result<(), DeSmuMEError> {
+        fs::File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+}
+
+/// The memory field and offset into it an ARM9 address is stored at.
+fn memory_location(addr: u32) -> Result<(&'static str, usize), DeSmuMEError> {
+    Ok(match addr {
+        0x00000000..=0x01FFFFFF => ("ITCM", (addr & 0x7FFF) as usize),
+        0x02000000..=0x02FFFFFF => ("WRAM", (addr & 0x3FFFFF) as usize),
+        0x05000000..=0x05FFFFFF => ("VMEM", (addr & 0x7FF) as usize),
+        0x06800000..=0x068A3FFF => ("LCDM", (addr - 0x06800000) as usize),
+        0x07000000..=0x07FFFFFF => ("OAMS", (addr & 0x7FF) as usize),
+        _ => return Err(DeSmuMEError::InvalidAddress(addr)),
+    })
+}
+
+/// The chunk, field name and value index a register is stored at.
+pub(crate) fn register_location(processor: &Processor, reg: &Register) -> (ChunkKind, String, usize) {
+    let (kind, prefix) = match processor {
+        Processor::Arm9 => (ChunkKind::Arm9, '9'),
+        Processor::Arm7 => (ChunkKind::Arm7, '7'),
+    };
+    match reg {
+        Register::CPSR => (kind, format!("{}CPS", prefix), 0),
+        Register::SPSR => (kind, format!("{}SPS", prefix), 0),
+        reg => (kind, format!("{}REG", prefix), reg.index().unwrap()),
+    }
+}
+
+fn parse_chunks(data: &[u8]) -> Result<Vec<Chunk>, DeSmuMEError> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        let id = read_u32(data, pos)?;
+        if id == END_OF_CHUNKS {
+            break;
+        }
+        let size = read_u32(data, pos + 4)? as usize;
+        let content = data.get(pos + 8..pos + 8 + size)
+            .ok_or_else(|| invalid(&format!("Chunk {} is truncated.", id)))?;
+        let kind = ChunkKind::from_id(id);
+        let data = match kind.has_fields().then(|| parse_fields(content)).flatten() {
+            Some(fields) => ChunkData::Fields(fields),
+            None => ChunkData::Raw(content.to_vec()),
+        };
+        chunks.push(Chunk { kind, data });
+        pos += 8 + size;
+    }
+    Ok(chunks)
+}
+
+/// Parse a list of fields. Returns `None` if the data is not a well-formed list of fields.
+fn parse_fields(data: &[u8]) -> Option<Vec<Field>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let name = data.get(pos..pos + 4)?.try_into().unwrap();
+        let size = read_u32(data, pos + 4).ok()?;
+        let count = read_u32(data, pos + 8).ok()?;
+        let len = (size as usize).checked_mul(count as usize)?;
+        let value = data.get(pos + 12..pos + 12 + len)?;
+        fields.push(Field { name, size, count, data: value.to_vec() });
+        pos += 12 + len;
+    }
+    Some(fields)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, DeSmuMEError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("Unexpected end of data."))
+}
+
+fn invalid(reason: &str) -> DeSmuMEError {
+    DeSmuMEError::InvalidSavestate(reason.to_owned())
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{DeSmuMEError, SCREEN_HEIGHT_BOTH, SCREEN_WIDTH};
+
+const MAGIC: &[u8; 8] = b"RSDMMETA";
+const VERSION: u32 = 1;
+
+/// Additional information stored in a sidecar file next to a savestate
+/// (`<savestate file>.meta`), eg. for showing savestates in a state picker.
+///
+/// Saving a savestate file always stores its metadata, which holds at least the frame counters.
+///
+/// See [`crate::LoadedDeSmuME::savestate_metadata`] to create it from the current emulator state.
+#[derive(Debug, Clone, Default)]
+pub struct SavestateMetadata {
+    /// Title of the ROM, from the cartridge header.
+    pub title: String,
+    /// Game code of the ROM, from the cartridge header.
+    pub game_code: String,
+    /// The frame counter at the time the savestate was saved. It is restored when loading the
+    /// savestate, see [`crate::LoadedDeSmuME::frame_count`].
+    pub frame: u64,
+    /// The lag frame counter at the time the savestate was saved, restored like `frame`.
+    pub lag_frames: u64,
+    /// A user supplied label.
+    pub label: String,
+    /// Both screens as RGBX color values (see [`crate::LoadedDeSmuME::display_buffer_as_rgbx`]),
+    /// if they were captured.
+    pub screens: Option<Vec<u8>>,
+}
+
+impl SavestateMetadata {
+    /// The path of the sidecar file for the given savestate file.
+    pub fn sidecar_path(savestate_path: &Path) -> PathBuf {
+        let mut path = savestate_path.as_os_str().to_owned();
+        path.push(".meta");
+        PathBuf::from(path)
+    }
+
+    /// Read the metadata for the given savestate file, `None` if there is no sidecar file.
+    pub fn read_for(savestate_path: &Path) -> Result<Option<Self>, DeSmuMEError> {
+        let path = Self::sidecar_path(savestate_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::parse(&fs::read(path)?).map(Some)
+    }
+
+    /// Write the metadata as sidecar file of the given savestate file.
+    pub fn write_for(&self, savestate_path: &Path) -> Result<(), DeSmuMEError> {
+        fs::write(Self::sidecar_path(savestate_path), self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Remove the sidecar file of the given savestate file, if it exists.
+    pub(crate) fn remove_for(savestate_path: &Path) {
+        // A missing sidecar is fine.
+        let _ = fs::remove_file(Self::sidecar_path(savestate_path));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(VERSION.to_le_bytes());
+        out.extend(self.frame.to_le_bytes());
+        out.extend(self.lag_frames.to_le_bytes());
+        for string in [&self.title, &self.game_code, &self.label] {
+            out.extend((string.len() as u32).to_le_bytes());
+            out.extend(string.as_bytes());
+        }
+        match &self.screens {
+            Some(screens) => {
+                out.push(1);
+                out.extend((screens.len() as u32).to_le_bytes());
+                out.extend(screens);
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, DeSmuMEError> {
+        let mut reader = Reader(data);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("Not a savestate metadata file."));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(invalid(&format!("Unsupported metadata version {}.", version)));
+        }
+        let frame = reader.u64()?;
+        let lag_frames = reader.u64()?;
+        let title = reader.string()?;
+        let game_code = reader.string()?;
+        let label = reader.string()?;
+        let screens = match reader.take(1)?[0] {
+            0 => None,
+            _ => {
+                let len = reader.u32()? as usize;
+                if len != SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4 {
+                    return Err(invalid("The screen capture has an unexpected size."));
+                }
+                Some(reader.take(len)?.to_vec())
+            }
+        };
+        Ok(Self { title, game_code, frame, lag_frames, label, screens })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeSmuMEError> {
+        if self.0.len() < len {
+            return Err(invalid("Unexpected end of data."));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DeSmuMEError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DeSmuMEError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, DeSmuMEError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("Invalid string."))
+    }
+}
+
+fn invalid(reason: &str) -> DeSmuMEError {
+    DeSmuMEError::InvalidSavestateMetadata(reason.to_owned())
+}
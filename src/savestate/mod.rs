@@ -0,0 +1,315 @@
+pub mod format;
+mod metadata;
+
+use std::cell::Cell;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use tempfile::NamedTempFile;
+use crate::{DeSmuMEError, FrameCounters, NB_STATES};
+use crate::ffi::*;
+use crate::frame::FrameCounter;
+pub use crate::savestate::format::SavestateFile;
+pub use crate::savestate::metadata::SavestateMetadata;
+
+/// Load and save savestates. Either slots can be used  (maximum number of slots is in the
+/// constant `NB_STATES`), or savestates can be directly loaded from / saved to files.
+pub struct DeSmuMESavestate {
+    /// Whether DeSmuME's slot information is up to date.
+    scanned: Cell<bool>,
+    /// Path of the currently loaded ROM, DeSmuME names the slot files after it.
+    rom_path: Option<PathBuf>,
+    /// Working directory at the time the ROM was opened.
+    work_dir: Option<PathBuf>,
+    counter: Rc<FrameCounter>,
+}
+
+/// Information about a used savestate slot.
+#[derive(Debug, Clone)]
+pub struct SlotInfo {
+    /// The slot ID.
+    pub slot_id: u8,
+    /// The date the savestate was last saved at.
+    pub date: SimpleDate,
+    /// Size of the savestate file in bytes, if the file could be located.
+    pub size: Option<u64>,
+    /// Metadata saved with [`DeSmuMESavestate::save_with_metadata`], if any.
+    pub metadata: Option<SavestateMetadata>,
+}
+
+impl DeSmuMESavestate {
+    pub(crate) fn new(counter: Rc<FrameCounter>) -> Self {
+        Self { scanned: Cell::new(false), rom_path: None, work_dir: None, counter }
+    }
+
+    /// Tell the savestate manager that a new ROM was opened, slots now refer to different files.
+    pub(crate) fn rom_opened(&mut self, rom_path: &Path) {
+        self.rom_path = Some(rom_path.to_path_buf());
+        self.work_dir = env::current_dir().ok();
+        self.scanned.set(false);
+    }
+
+    /// Scan all savestate slots for if they exist or not.
+    /// This is done automatically when needed, but can be used to pick up
+    /// slot files that were changed outside of the emulator.
+    pub fn scan(&mut self) {
+        self.ensure_scanned(true);
+    }
+
+    /// Returns whether or not a savestate in the specified slot exists.
+    pub fn exists(&self, slot_id: u8) -> Result<bool, DeSmuMEError> {
+        check_slot(slot_id)?;
+        self.ensure_scanned(false);
+        Ok(unsafe { desmume_savestate_slot_exists(slot_id as c_int) > 0 })
+    }
+
+    /// Load the savestate in the specified slot.
+    /// Returns an error if the slot is empty.
+    ///
+    /// The frame counters are restored from the metadata of the slot, if it can be found.
+    pub fn load(&mut self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        if !self.exists(slot_id)? {
+            return Err(DeSmuMEError::SavestateSlotEmpty(slot_id));
+        }
+        unsafe { desmume_savestate_slot_load(slot_id as c_int) }
+        if let Some(path) = self.slot_path(slot_id) {
+            self.restore_counters(&path);
+        }
+        Ok(())
+    }
+
+    /// Save the current game state to the savestate in the specified slot.
+    ///
+    /// Metadata holding only the frame counters is stored next to it, if the location of the
+    /// slot file can be determined.
+    pub fn save(&mut self, slot_id: u8) -> Result<(), DeSmuMEError> {
+        check_slot(slot_id)?;
+        unsafe { desmume_savestate_slot_save(slot_id as c_int) }
+        self.scanned.set(false);
+        // Only store the metadata if the guessed slot path is right.
+        match self.slot_path(slot_id).filter(|path| path.exists()) {
+            Some(path) => self.write_metadata(&path, SavestateMetadata::default()),
+            None => Ok(()),
+        }
+    }
+
+    /// Save the current game state to the savestate in the specified slot and store the given
+    /// metadata, with the current frame counters, next to it. Fails if the location of the slot
+    /// file can not be determined.
+    pub fn save_with_metadata(&mut self, slot_id: u8, metadata: &SavestateMetadata) -> Result<(), DeSmuMEError> {
+        check_slot(slot_id)?;
+        let path = self.slot_path(slot_id).ok_or(DeSmuMEError::SavestateSlotPathUnknown(slot_id))?;
+        unsafe { desmume_savestate_slot_save(slot_id as c_int) }
+        self.scanned.set(false);
+        self.write_metadata(&path, metadata.clone())
+    }
+
+    /// Returns information about the savestate in the specified slot,
+    /// or `None` if the slot is empty.
+    pub fn slot_info(&self, slot_id: u8) -> Result<Option<SlotInfo>, DeSmuMEError> {
+        if !self.exists(slot_id)? {
+            return Ok(None);
+        }
+        let date = unsafe { CStr::from_ptr(desmume_savestate_slot_date(slot_id as c_int)) };
+        let date = date.to_str()
+            .ok()
+            .and_then(parse_slot_date)
+            .ok_or_else(|| DeSmuMEError::InvalidSavestateDate(date.to_string_lossy().into_owned()))?;
+        let path = self.slot_path(slot_id);
+        let size = path.as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .map(|meta| meta.len());
+        let metadata = match &path {
+            Some(path) => SavestateMetadata::read_for(path)?,
+            None => None,
+        };
+        Ok(Some(SlotInfo { slot_id, date, size, metadata }))
+    }
+
+    /// The path DeSmuME stores the savestate for the given slot at, if it can be determined.
+    ///
+    /// DeSmuME names slot files after the ROM (`<rom name>.ds<slot>`). They are placed in the
+    /// working directory, or in the `States` directory next to the executable on Windows.
+    ///
+    /// The interface of DeSmuME does not expose the directory it uses, so this is a guess based
+    /// on its default path settings. If DeSmuME was configured to use another directory, the
+    /// returned path does not exist and slot sizes, metadata and frame counters are not available.
+    pub fn slot_path(&self, slot_id: u8) -> Option<PathBuf> {
+        let rom_name = self.rom_path.as_ref()?.file_stem()?.to_str()?;
+        let dir = if cfg!(windows) {
+            env::current_exe().ok()?.parent()?.join("States")
+        } else {
+            self.work_dir.clone()?
+        };
+        Some(dir.join(format!("{}.ds{}", rom_name, slot_id)))
+    }
+
+    fn ensure_scanned(&self, force: bool) {
+        if force || !self.scanned.get() {
+            unsafe { desmume_savestate_scan() }
+            self.scanned.set(true);
+        }
+    }
+
+    /// Load a savestate from file.
+    ///
+    /// The frame counters are restored from the metadata stored next to the file, if it has any.
+    pub fn load_file(&mut self, file_name: &str) -> Result<(), DeSmuMEError> {
+        load_file(file_name)?;
+        self.restore_counters(Path::new(file_name));
+        Ok(())
+    }
+
+    /// Save a savestate to file.
+    ///
+    /// Metadata holding only the frame counters is stored next to it, replacing the metadata of
+    /// a previous savestate.
+    pub fn save_file(&mut self, file_name: &str) -> Result<(), DeSmuMEError> {
+        save_file(file_name)?;
+        self.write_metadata(Path::new(file_name), SavestateMetadata::default())
+    }
+
+    /// Save a savestate to file and store the given metadata, with the current frame counters,
+    /// next to it.
+    pub fn save_file_with_metadata(&mut self, file_name: &str, metadata: &SavestateMetadata) -> Result<(), DeSmuMEError> {
+        save_file(file_name)?;
+        self.write_metadata(Path::new(file_name), metadata.clone())
+    }
+
+    /// Returns the metadata stored next to a savestate file, if any.
+    pub fn file_metadata(&self, file_name: &str) -> Result<Option<SavestateMetadata>, DeSmuMEError> {
+        SavestateMetadata::read_for(Path::new(file_name))
+    }
+
+    /// Load a savestate from an in-memory buffer, as returned by [`DeSmuMESavestate::save_to_vec`].
+    ///
+    /// DeSmuME can only load savestates from disk, so the buffer is passed through a temporary file.
+    /// The frame counters are left unchanged, see [`DeSmuMESavestate::load_from_slice_with_counters`].
+    pub fn load_from_slice(&mut self, data: &[u8]) -> Result<(), DeSmuMEError> {
+        load_from_slice(data)
+    }
+
+    /// Load a savestate from an in-memory buffer and set the frame counters, as returned by
+    /// [`DeSmuMESavestate::save_to_vec_with_counters`].
+    pub fn load_from_slice_with_counters(&mut self, data: &[u8], counters: FrameCounters) -> Result<(), DeSmuMEError> {
+        load_from_slice(data)?;
+        self.counter.set(counters);
+        Ok(())
+    }
+
+    /// Save the current game state to an in-memory buffer, which holds the savestate exactly as
+    /// DeSmuME writes it.
+    ///
+    /// DeSmuME can only write savestates to disk, so the state is passed through a temporary file.
+    /// The frame counters are not part of the savestate, see
+    /// [`DeSmuMESavestate::save_to_vec_with_counters`].
+    pub fn save_to_vec(&mut self) -> Result<Vec<u8>, DeSmuMEError> {
+        save_to_vec()
+    }
+
+    /// Save the current game state to an in-memory buffer like [`DeSmuMESavestate::save_to_vec`],
+    /// and return the current frame counters with it.
+    pub fn save_to_vec_with_counters(&mut self) -> Result<(Vec<u8>, FrameCounters), DeSmuMEError> {
+        Ok((save_to_vec()?, self.counter.get()))
+    }
+
+    /// Store `metadata` with the current frame counters next to the savestate at `path`.
+    fn write_metadata(&self, path: &Path, metadata: SavestateMetadata) -> Result<(), DeSmuMEError> {
+        let counters = self.counter.get();
+        let metadata = SavestateMetadata { frame: counters.frames, lag_frames: counters.lag_frames, ..metadata };
+        let result = metadata.write_for(path);
+        if result.is_err() {
+            // Stale metadata would restore the counters of the previous savestate.
+            SavestateMetadata::remove_for(path);
+        }
+        result
+    }
+
+    /// Restore the frame counters from the metadata stored next to the savestate at `path`.
+    /// The counters are left unchanged, if there is no valid metadata.
+    fn restore_counters(&self, path: &Path) {
+        if let Ok(Some(metadata)) = SavestateMetadata::read_for(path) {
+            self.counter.set(FrameCounters { frames: metadata.frame, lag_frames: metadata.lag_frames });
+        }
+    }
+}
+
+pub(crate) fn load_from_slice(data: &[u8]) -> Result<(), DeSmuMEError> {
+    let map_err = |e: std::io::Error| DeSmuMEError::LoadSavestateFailed(format!("Failed to write temporary file: {}", e));
+    let mut file = NamedTempFile::new().map_err(map_err)?;
+    file.write_all(data).map_err(map_err)?;
+    file.flush().map_err(map_err)?;
+    load_file(temp_file_name(file.path(), DeSmuMEError::LoadSavestateFailed)?)
+}
+
+pub(crate) fn save_to_vec() -> Result<Vec<u8>, DeSmuMEError> {
+    let map_err = |e: std::io::Error| DeSmuMEError::SaveSavestateFailed(format!("Failed to use temporary file: {}", e));
+    let file = NamedTempFile::new().map_err(map_err)?;
+    save_file(temp_file_name(file.path(), DeSmuMEError::SaveSavestateFailed)?)?;
+    fs::read(file.path()).map_err(map_err)
+}
+
+fn load_file(file_name: &str) -> Result<(), DeSmuMEError> {
+    unsafe {
+        if desmume_savestate_load(CString::new(file_name)?.as_ptr()) <= 0 {
+            Err(DeSmuMEError::LoadSavestateFailed(format!("DeSmuME could not load '{}'.", file_name)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn save_file(file_name: &str) -> Result<(), DeSmuMEError> {
+    unsafe {
+        if desmume_savestate_save(CString::new(file_name)?.as_ptr()) <= 0 {
+            Err(DeSmuMEError::SaveSavestateFailed(format!("DeSmuME could not write '{}'.", file_name)))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn temp_file_name(path: &Path, err: fn(String) -> DeSmuMEError) -> Result<&str, DeSmuMEError> {
+    path.to_str().ok_or_else(|| err(format!("Temporary file path '{}' is not valid UTF-8.", path.display())))
+}
+
+fn check_slot(slot_id: u8) -> Result<(), DeSmuMEError> {
+    if slot_id as usize >= NB_STATES {
+        Err(DeSmuMEError::InvalidSavestateSlot(slot_id))
+    } else {
+        Ok(())
+    }
+}
+
+impl FromStr for SimpleDate {
+    type Err = DeSmuMEError;
+
+    /// Parse a date in the format DeSmuME reports savestate slot dates in,
+    /// `%d-%b-%Y %H:%M:%S` (eg. `07-Mar-2023 14:05:09`).
+    fn from_str(date: &str) -> Result<Self, Self::Err> {
+        parse_slot_date(date).ok_or_else(|| DeSmuMEError::InvalidSavestateDate(date.to_owned()))
+    }
+}
+
+/// Parse the slot date DeSmuME reports, which is formatted as `%d-%b-%Y %H:%M:%S`.
+fn parse_slot_date(date: &str) -> Option<SimpleDate> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (day_part, time_part) = date.trim().split_once(' ')?;
+    let mut day_part = day_part.splitn(3, '-');
+    let day = day_part.next()?.parse().ok()?;
+    let month = day_part.next()?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as c_int + 1;
+    let year = day_part.next()?.parse().ok()?;
+    let mut time_part = time_part.splitn(3, ':');
+    let hour = time_part.next()?.parse().ok()?;
+    let minute = time_part.next()?.parse().ok()?;
+    let second = time_part.next()?.parse().ok()?;
+    if !(1..=31).contains(&day) || !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..=60).contains(&second) {
+        return None;
+    }
+    Some(SimpleDate { year, month, day, hour, minute, second, millisecond: 0 })
+}
@@ -0,0 +1,140 @@
+//! Saving screenshots, see [`crate::LoadedDeSmuME::screenshot`].
+
+use std::io::{self, Write};
+use std::path::Path;
+use crate::compositor::{Compositor, RgbaImage};
+use crate::framebuffer::{Frame, Screen};
+use crate::DeSmuMEError;
+
+/// The screens to capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenSelection {
+    Top,
+    Bottom,
+    /// Both screens, the top screen above the bottom screen.
+    Both
+}
+
+/// Image format of a screenshot.
+///
+/// Which variants exist depends on the enabled features, so matches need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageFormat {
+    /// Binary PPM (`P6`).
+    Ppm,
+    /// Uncompressed 24 bit BMP.
+    Bmp,
+    /// PNG, requires the `png` feature.
+    #[cfg(feature = "png")]
+    Png
+}
+
+impl ImageFormat {
+    /// Determine the format from the file extension of `path`.
+    pub fn from_path(path: &Path) -> Result<Self, DeSmuMEError> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ppm") => Ok(Self::Ppm),
+            Some("bmp") => Ok(Self::Bmp),
+            #[cfg(feature = "png")]
+            Some("png") => Ok(Self::Png),
+            _ => Err(DeSmuMEError::UnsupportedImageFormat(path.to_path_buf()))
+        }
+    }
+}
+
+impl Frame {
+    /// Returns the selected screens.
+    pub fn select(&self, screens: ScreenSelection) -> Screen<'_> {
+        match screens {
+            ScreenSelection::Top => self.top(),
+            ScreenSelection::Bottom => self.bottom(),
+            ScreenSelection::Both => self.both()
+        }
+    }
+}
+
+impl Screen<'_> {
+    /// Encode the screen as an image.
+    pub fn write_image<W: Write>(&self, writer: W, format: ImageFormat) -> Result<(), DeSmuMEError> {
+        RgbaImage::from(*self).write_image(writer, format)
+    }
+}
+
+impl RgbaImage {
+    /// Encode the image. The alpha channel is dropped.
+    pub fn write_image<W: Write>(&self, writer: W, format: ImageFormat) -> Result<(), DeSmuMEError> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm(writer),
+            ImageFormat::Bmp => self.write_bmp(writer),
+            #[cfg(feature = "png")]
+            ImageFormat::Png => self.write_png(writer)
+        }
+    }
+
+    fn to_rgb888(&self) -> Vec<u8> {
+        self.data().chunks_exact(4).flat_map(|pixel| &pixel[..3]).copied().collect()
+    }
+
+    fn write_ppm<W: Write>(&self, mut writer: W) -> Result<(), DeSmuMEError> {
+        write!(writer, "P6\n{} {}\n255\n", self.width(), self.height())?;
+        writer.write_all(&self.to_rgb888())?;
+        Ok(writer.flush()?)
+    }
+
+    fn write_bmp<W: Write>(&self, mut writer: W) -> Result<(), DeSmuMEError> {
+        const HEADER_SIZE: u32 = 14 + 40;
+        // Lines are padded to a multiple of 4 bytes.
+        let stride = (self.width() * 3 + 3) & !3;
+        let data_size = (stride * self.height()) as u32;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend(b"BM");
+        header.extend((HEADER_SIZE + data_size).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend(HEADER_SIZE.to_le_bytes());
+        header.extend(40u32.to_le_bytes());
+        header.extend((self.width() as i32).to_le_bytes());
+        header.extend((self.height() as i32).to_le_bytes());
+        header.extend(1u16.to_le_bytes());
+        header.extend(24u16.to_le_bytes());
+        // No compression, the data size, 72 DPI and no palette.
+        header.extend(0u32.to_le_bytes());
+        header.extend(data_size.to_le_bytes());
+        header.extend(2835u32.to_le_bytes());
+        header.extend(2835u32.to_le_bytes());
+        header.extend([0; 8]);
+        writer.write_all(&header)?;
+
+        // Lines are stored bottom to top, pixels as BGR.
+        let rgb = self.to_rgb888();
+        let mut line = vec![0; stride];
+        for y in (0..self.height()).rev() {
+            let pixels = &rgb[y * self.width() * 3..(y + 1) * self.width() * 3];
+            for (out, pixel) in line.chunks_exact_mut(3).zip(pixels.chunks_exact(3)) {
+                out.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+            writer.write_all(&line)?;
+        }
+        Ok(writer.flush()?)
+    }
+
+    #[cfg(feature = "png")]
+    fn write_png<W: Write>(&self, writer: W) -> Result<(), DeSmuMEError> {
+        let to_io = |e: png::EncodingError| io::Error::other(e);
+        let mut encoder = png::Encoder::new(writer, self.width() as u32, self.height() as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(to_io)?;
+        writer.write_image_data(&self.to_rgb888()).map_err(to_io)?;
+        Ok(writer.finish().map_err(to_io)?)
+    }
+}
+
+pub(crate) fn save(frame: &Frame, path: &Path, compositor: &Compositor) -> Result<(), DeSmuMEError> {
+    let format = ImageFormat::from_path(path)?;
+    let image = compositor.compose(frame)?;
+    let file = io::BufWriter::new(std::fs::File::create(path)?);
+    image.write_image(file, format)
+}
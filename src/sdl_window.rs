@@ -7,6 +7,9 @@ use crate::ffi::*;
 ///
 /// This is meant to be a simple way to use and test the library, for intergration in custom UIs
 /// you probably want to process input and display manually.
+///
+/// The window always shows the screens in DeSmuME's native layout. It does not use the
+/// [`Compositor`](crate::Compositor).
 pub struct DeSmuMESdlWindow(PhantomData<()>);
 
 impl DeSmuMESdlWindow {
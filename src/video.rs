@@ -0,0 +1,249 @@
+//! Recording the emulator output as uncompressed video, see
+//! [`crate::LoadedDeSmuME::start_recording`].
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::compositor::{Compositor, RgbaImage};
+use crate::{DeSmuMEError, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Frame rate of the DS as a fraction, 59.8261 frames per second.
+const FRAME_RATE: (u32, u32) = (598261, 10000);
+
+/// Container format of a video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// YUV4MPEG2 with 4:4:4 chroma, so that colors are not subsampled.
+    Y4m,
+    /// AVI with uncompressed 24 bit RGB frames. AVI files are limited to 4 GB.
+    Avi
+}
+
+impl VideoFormat {
+    /// Determine the format from the file extension of `path`.
+    pub fn from_path(path: &Path) -> Result<Self, DeSmuMEError> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("y4m") => Ok(Self::Y4m),
+            Some("avi") => Ok(Self::Avi),
+            _ => Err(DeSmuMEError::UnsupportedVideoFormat(path.to_path_buf()))
+        }
+    }
+}
+
+/// Writes composed frames to a video file.
+pub(crate) struct VideoRecorder {
+    file: BufWriter<File>,
+    format: VideoFormat,
+    compositor: Compositor,
+    width: usize,
+    height: usize,
+    frames: u32,
+    /// The first error while writing, after which no further frames are written.
+    error: Option<DeSmuMEError>,
+    finished: bool
+}
+
+impl VideoRecorder {
+    pub(crate) fn create(path: &Path, compositor: Compositor) -> Result<Self, DeSmuMEError> {
+        let format = VideoFormat::from_path(path)?;
+        if compositor.scale == 0 {
+            return Err(DeSmuMEError::InvalidCompositorScale);
+        }
+        let (width, height) = compositor.size(SCREEN_WIDTH, SCREEN_HEIGHT);
+        let mut recorder = Self {
+            file: BufWriter::new(File::create(path)?),
+            format,
+            compositor,
+            width,
+            height,
+            frames: 0,
+            error: None,
+            finished: false
+        };
+        match format {
+            VideoFormat::Y4m => writeln!(
+                recorder.file, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444", width, height, FRAME_RATE.0, FRAME_RATE.1
+            )?,
+            VideoFormat::Avi => recorder.file.write_all(&recorder.avi_header())?
+        }
+        Ok(recorder)
+    }
+
+    pub(crate) fn compositor(&self) -> &Compositor {
+        &self.compositor
+    }
+
+    /// Write a frame. Errors are kept until the recording is finished.
+    pub(crate) fn write_frame(&mut self, image: Result<RgbaImage, DeSmuMEError>) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = image.and_then(|image| match self.format {
+            VideoFormat::Y4m => self.write_y4m_frame(&image),
+            VideoFormat::Avi => self.write_avi_frame(&image)
+        });
+        match result {
+            Ok(()) => self.frames += 1,
+            Err(e) => self.error = Some(e)
+        }
+    }
+
+    /// Finish the file and return the first error that occurred while recording.
+    pub(crate) fn finish(mut self) -> Result<u32, DeSmuMEError> {
+        self.finished = true;
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if self.format == VideoFormat::Avi {
+            self.finish_avi()?;
+        }
+        self.file.flush()?;
+        Ok(self.frames)
+    }
+
+    fn write_y4m_frame(&mut self, image: &RgbaImage) -> Result<(), DeSmuMEError> {
+        let pixels = image.width() * image.height();
+        let mut frame = vec![0; pixels * 3];
+        for (i, pixel) in image.data().chunks_exact(4).enumerate() {
+            let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+            // BT.601, limited range.
+            frame[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            frame[pixels + i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            frame[pixels * 2 + i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.file.write_all(b"FRAME\n")?;
+        Ok(self.file.write_all(&frame)?)
+    }
+
+    // The AVI file is laid out as follows, sizes and frame counts are filled in when finishing:
+    //   RIFF 'AVI ' (LIST 'hdrl' (avih, LIST 'strl' (strh, strf)), LIST 'movi' (00db...), idx1)
+
+    /// Offset of the size of the RIFF chunk.
+    const AVI_RIFF_SIZE: u64 = 4;
+    /// Offset of the total frames in the main header.
+    const AVI_TOTAL_FRAMES: u64 = 48;
+    /// Offset of the length of the video stream.
+    const AVI_STREAM_LENGTH: u64 = 140;
+    /// Offset of the size of the 'movi' list.
+    const AVI_MOVI_SIZE: u64 = 216;
+    /// Size of the headers, up to the first frame.
+    const AVI_HEADER_SIZE: u32 = 224;
+
+    fn avi_stride(&self) -> usize {
+        // Lines are padded to a multiple of 4 bytes.
+        (self.width * 3 + 3) & !3
+    }
+
+    fn avi_frame_size(&self) -> u32 {
+        (self.avi_stride() * self.height) as u32
+    }
+
+    fn avi_header(&self) -> Vec<u8> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        let frame_size = self.avi_frame_size();
+        let mut out = Vec::with_capacity(Self::AVI_HEADER_SIZE as usize);
+        let put = |out: &mut Vec<u8>, value: u32| out.extend(value.to_le_bytes());
+
+        out.extend(b"RIFF");
+        put(&mut out, 0);
+        out.extend(b"AVI LIST");
+        put(&mut out, 192);
+        out.extend(b"hdrlavih");
+        put(&mut out, 56);
+        put(&mut out, (1_000_000u64 * FRAME_RATE.1 as u64 / FRAME_RATE.0 as u64) as u32);
+        put(&mut out, (frame_size as u64 * FRAME_RATE.0 as u64 / FRAME_RATE.1 as u64) as u32);
+        // Padding granularity, flags (has index), total frames, initial frames, streams,
+        // suggested buffer size, width, height and reserved fields.
+        for value in [0, 0x10, 0, 0, 1, frame_size, width, height, 0, 0, 0, 0] {
+            put(&mut out, value);
+        }
+
+        out.extend(b"LIST");
+        put(&mut out, 116);
+        out.extend(b"strlstrh");
+        put(&mut out, 56);
+        out.extend(b"vidsDIB ");
+        // Flags, priority and language, initial frames, scale, rate, start, length,
+        // suggested buffer size, quality (default) and sample size.
+        for value in [0, 0, 0, FRAME_RATE.1, FRAME_RATE.0, 0, 0, frame_size, u32::MAX, 0] {
+            put(&mut out, value);
+        }
+        for value in [0, 0, width as u16, height as u16] {
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(b"strf");
+        put(&mut out, 40);
+        // BITMAPINFOHEADER, a positive height means that lines are stored bottom to top.
+        put(&mut out, 40);
+        put(&mut out, width);
+        put(&mut out, height);
+        out.extend(1u16.to_le_bytes());
+        out.extend(24u16.to_le_bytes());
+        for value in [0, frame_size, 0, 0, 0, 0] {
+            put(&mut out, value);
+        }
+
+        out.extend(b"LIST");
+        put(&mut out, 0);
+        out.extend(b"movi");
+        out
+    }
+
+    fn write_avi_frame(&mut self, image: &RgbaImage) -> Result<(), DeSmuMEError> {
+        let frame_size = self.avi_frame_size();
+        let index_size = 8 + 16 * (self.frames as u64 + 1);
+        let file_size = Self::AVI_HEADER_SIZE as u64 + (8 + frame_size as u64) * (self.frames as u64 + 1) + index_size;
+        if file_size > u32::MAX as u64 {
+            return Err(DeSmuMEError::VideoTooLarge);
+        }
+        let stride = self.avi_stride();
+        let mut frame = vec![0; frame_size as usize];
+        for (y, line) in frame.chunks_exact_mut(stride).enumerate() {
+            let row = self.height - 1 - y;
+            let pixels = &image.data()[row * self.width * 4..(row + 1) * self.width * 4];
+            for (out, pixel) in line.chunks_exact_mut(3).zip(pixels.chunks_exact(4)) {
+                out.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+        }
+        self.file.write_all(b"00db")?;
+        self.file.write_all(&frame_size.to_le_bytes())?;
+        Ok(self.file.write_all(&frame)?)
+    }
+
+    fn finish_avi(&mut self) -> Result<(), DeSmuMEError> {
+        let frame_size = self.avi_frame_size();
+        let movi_size = 4 + (8 + frame_size) * self.frames;
+        self.file.write_all(b"idx1")?;
+        self.file.write_all(&(16 * self.frames).to_le_bytes())?;
+        for frame in 0..self.frames {
+            // Offsets are relative to the 'movi' identifier.
+            let offset = 4 + (8 + frame_size) * frame;
+            self.file.write_all(b"00db")?;
+            for value in [0x10, offset, frame_size] {
+                self.file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        let riff_size = Self::AVI_HEADER_SIZE - 8 + movi_size - 4 + 8 + 16 * self.frames;
+        for (offset, value) in [
+            (Self::AVI_RIFF_SIZE, riff_size),
+            (Self::AVI_TOTAL_FRAMES, self.frames),
+            (Self::AVI_STREAM_LENGTH, self.frames),
+            (Self::AVI_MOVI_SIZE, movi_size)
+        ] {
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+impl Drop for VideoRecorder {
+    fn drop(&mut self) {
+        // Keep the file playable, if the recording was not stopped.
+        if !self.finished && self.error.is_none() && self.format == VideoFormat::Avi {
+            let _ = self.finish_avi();
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use rs_desmume::{DeSmuME, DeSmuMEError, SaveType};
+
+#[test]
+fn test_save_type_sizes() {
+    assert_eq!(None, SaveType::Auto.size());
+    assert_eq!(Some(512), SaveType::Eeprom4k.size());
+    assert_eq!(Some(8 * 1024), SaveType::Eeprom64k.size());
+    assert_eq!(Some(64 * 1024), SaveType::Eeprom512k.size());
+    assert_eq!(Some(32 * 1024), SaveType::Fram256k.size());
+    assert_eq!(Some(256 * 1024), SaveType::Flash2m.size());
+    assert_eq!(Some(64 * 1024 * 1024), SaveType::Flash512m.size());
+
+    for size in [512, 8 * 1024, 64 * 1024, 32 * 1024, 256 * 1024, 64 * 1024 * 1024] {
+        assert_eq!(Some(size), SaveType::from_size(size).unwrap().size());
+    }
+    assert_eq!(None, SaveType::from_size(0));
+    assert_eq!(None, SaveType::from_size(1000));
+}
+
+#[test]
+fn test_backup_import_errors() {
+    let mut emu = DeSmuME::init().unwrap()
+        .with_savetype(SaveType::Eeprom64k)
+        .open("tests/touchtest.nds", false)
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let missing = dir.path().join("missing.sav");
+    assert!(matches!(emu.backup_mut().import_file(&missing), Err(DeSmuMEError::Io(_))));
+
+    // A file with a DeSmuME footer is parsed as .dsv file, a damaged one is rejected.
+    let damaged = dir.path().join("damaged.dsv");
+    let mut dsv = vec![0; 100];
+    dsv.extend(b"|-DESMUME SAVE-|");
+    fs::write(&damaged, dsv).unwrap();
+    assert!(matches!(emu.backup_mut().import_file(&damaged), Err(DeSmuMEError::InvalidBackup(_))));
+}
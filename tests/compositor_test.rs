@@ -0,0 +1,62 @@
+use rs_desmume::compositor::{DsScreen, Layout, Rotation};
+use rs_desmume::{Compositor, DeSmuMEError, Frame};
+
+const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+const GAP: [u8; 4] = [1, 2, 3, 4];
+
+fn frame() -> Frame {
+    // 2x1 screens, the top screen red and the bottom screen blue.
+    Frame::new(vec![0x001F, 0x001F, 0x7C00, 0x7C00], 2, 1).unwrap()
+}
+
+fn compositor(layout: Layout) -> Compositor {
+    Compositor { layout, gap: 1, gap_color: GAP, ..Default::default() }
+}
+
+#[test]
+fn test_layouts() {
+    let vertical = compositor(Layout::Vertical).compose(&frame()).unwrap();
+    assert_eq!((vertical.width(), vertical.height()), (2, 3));
+    assert_eq!(vertical.pixel(1, 0), Some(RED));
+    assert_eq!(vertical.pixel(1, 1), Some(GAP));
+    assert_eq!(vertical.pixel(1, 2), Some(BLUE));
+
+    let horizontal = compositor(Layout::Horizontal).compose(&frame()).unwrap();
+    assert_eq!((horizontal.width(), horizontal.height()), (5, 1));
+    assert_eq!(horizontal.pixel(2, 0), Some(GAP));
+    assert_eq!(horizontal.pixel(3, 0), Some(BLUE));
+
+    let hybrid = compositor(Layout::Hybrid { large: DsScreen::Bottom }).compose(&frame()).unwrap();
+    assert_eq!((hybrid.width(), hybrid.height()), (7, 2));
+    assert_eq!(hybrid.pixel(3, 1), Some(BLUE));
+    assert_eq!(hybrid.pixel(4, 0), Some(GAP));
+    assert_eq!(hybrid.pixel(5, 0), Some(GAP));
+    assert_eq!(hybrid.pixel(6, 1), Some(RED));
+
+    let single = compositor(Layout::Single(DsScreen::Bottom)).compose(&frame()).unwrap();
+    assert_eq!(single.data(), [BLUE, BLUE].concat());
+}
+
+#[test]
+fn test_rotation_and_scale() {
+    for (rotation, top_left) in [(Rotation::Deg90, BLUE), (Rotation::Deg180, BLUE), (Rotation::Deg270, RED)] {
+        let compositor = Compositor { rotation, scale: 2, ..Default::default() };
+        let image = compositor.compose(&frame()).unwrap();
+        assert_eq!((image.width(), image.height()), compositor.size(2, 1));
+        assert_eq!(image.pixel(0, 0), Some(top_left));
+        assert_eq!(image.pixel(1, 1), Some(top_left));
+    }
+    assert_eq!(Compositor { rotation: Rotation::Deg90, ..Default::default() }.size(256, 192), (384, 256));
+
+    let invalid = Compositor { scale: 0, ..Default::default() };
+    assert!(matches!(invalid.compose(&frame()), Err(DeSmuMEError::InvalidCompositorScale)));
+}
+
+#[test]
+fn test_compose_rgbx() {
+    let rgbx = [[0xFF, 0, 0, 0], [0xFF, 0, 0, 0], [0, 0, 0xFF, 0], [0, 0, 0xFF, 0]].concat();
+    let image = Compositor::default().compose_rgbx(&rgbx, 2, 1).unwrap();
+    assert_eq!(image, Compositor::default().compose(&frame()).unwrap());
+    assert!(matches!(Compositor::default().compose_rgbx(&rgbx[..8], 2, 1), Err(DeSmuMEError::BufferTooSmall { .. })));
+}
@@ -0,0 +1,49 @@
+use rs_desmume::backup::dsv::{dsv_to_sav, sav_to_dsv, DsvFile};
+use rs_desmume::{DeSmuMEError, SaveType};
+
+#[test]
+fn test_sav_dsv_roundtrip() {
+    let sav: Vec<u8> = (0..0x10000).map(|i| i as u8).collect();
+    let dsv = sav_to_dsv(&sav, SaveType::Auto).unwrap();
+    assert!(DsvFile::is_dsv(&dsv));
+    assert!(dsv.ends_with(b"|-DESMUME SAVE-|"));
+
+    let parsed = DsvFile::parse(&dsv).unwrap();
+    assert_eq!(SaveType::Eeprom512k, parsed.info.save_type);
+    assert_eq!(0x10000, parsed.info.mem_size);
+    assert_eq!(sav, dsv_to_sav(&dsv).unwrap());
+}
+
+#[test]
+fn test_sav_padding_and_validation() {
+    let dsv = sav_to_dsv(&[1, 2, 3], SaveType::Eeprom4k).unwrap();
+    let sav = dsv_to_sav(&dsv).unwrap();
+    assert_eq!(512, sav.len());
+    assert_eq!(&[1, 2, 3, 0xFF], &sav[..4]);
+
+    assert!(sav_to_dsv(&[0; 1000], SaveType::Auto).is_err());
+    assert!(sav_to_dsv(&[0; 1000], SaveType::Eeprom4k).is_err());
+    assert!(dsv_to_sav(&[0; 1000]).is_err());
+
+    let mut truncated = dsv.clone();
+    truncated.drain(..100);
+    assert!(dsv_to_sav(&truncated).is_err());
+}
+
+#[test]
+fn test_dsv_to_bytes_validation() {
+    let dsv = DsvFile::from_raw(&[1, 2, 3], SaveType::Eeprom4k).unwrap();
+    assert_eq!(dsv.to_bytes().unwrap(), sav_to_dsv(&[1, 2, 3], SaveType::Eeprom4k).unwrap());
+
+    let mut auto = dsv.clone();
+    auto.info.save_type = SaveType::Auto;
+    assert!(matches!(auto.to_bytes(), Err(DeSmuMEError::InvalidBackup(_))));
+
+    let mut mismatched = dsv.clone();
+    mismatched.info.save_type = SaveType::Flash2m;
+    assert!(matches!(mismatched.to_bytes(), Err(DeSmuMEError::InvalidBackup(_))));
+
+    let mut truncated = dsv.clone();
+    truncated.data.truncate(100);
+    assert!(matches!(truncated.to_bytes(), Err(DeSmuMEError::InvalidBackup(_))));
+}
@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use rs_desmume::{DeSmuME, FrameCounters, SavestateMetadata, SpeedMode};
+
+const KEYINPUT: u32 = 0x04000130;
+
+static KEYPAD_READS: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn count_keypad_read(_addr: u32, _size: i32) -> i32 {
+    KEYPAD_READS.fetch_add(1, Ordering::Relaxed);
+    1
+}
+
+#[test]
+fn test_frame_counters() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+    assert_eq!(emu.frame_counters(), FrameCounters::default());
+
+    // A user callback on KEYINPUT is chained after the lag frame detection.
+    emu.memory_mut().register_read(KEYINPUT, 2, Some(count_keypad_read));
+    let lag_changes = Rc::new(RefCell::new(Vec::new()));
+    let changes = lag_changes.clone();
+    let mut last_lag_frames = 0;
+    let hook = emu.on_frame_end(move |emu| {
+        changes.borrow_mut().push((emu.lag_frame_count() - last_lag_frames, emu.is_lag_frame()));
+        last_lag_frames = emu.lag_frame_count();
+    });
+    emu.run_frames(10);
+    drop(hook);
+    assert_eq!(emu.frame_count(), 10);
+    // Exactly the frames reported as lag frames increase the lag frame counter.
+    assert_eq!(lag_changes.borrow().len(), 10);
+    for &(increase, lagged) in lag_changes.borrow().iter() {
+        assert_eq!(increase, lagged as u64);
+    }
+    // Every frame that was not a lag frame read the keypad, which also ran the user callback.
+    assert!(KEYPAD_READS.load(Ordering::Relaxed) >= emu.frame_count() - emu.lag_frame_count());
+    emu.memory_mut().register_read(KEYINPUT, 2, None);
+
+    // In-memory savestates return the counters separately.
+    let saved = emu.frame_counters();
+    assert_eq!(saved, FrameCounters { frames: 10, lag_frames: emu.lag_frame_count() });
+    let (state, counters) = emu.savestate_mut().save_to_vec_with_counters().unwrap();
+    assert_eq!(counters, saved);
+    emu.run_frames(5);
+    assert_eq!(emu.frame_count(), 15);
+    emu.savestate_mut().load_from_slice(&state).unwrap();
+    assert_eq!(emu.frame_count(), 15);
+    emu.savestate_mut().load_from_slice_with_counters(&state, counters).unwrap();
+    assert_eq!(emu.frame_counters(), saved);
+
+    // Savestate files store the counters in their metadata.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counters.dst");
+    let file_name = path.to_str().unwrap();
+    emu.savestate_mut().save_file(file_name).unwrap();
+    let metadata = emu.savestate().file_metadata(file_name).unwrap().unwrap();
+    assert_eq!((metadata.frame, metadata.lag_frames), (saved.frames, saved.lag_frames));
+    emu.run_frames(5);
+    emu.savestate_mut().load_file(file_name).unwrap();
+    assert_eq!(emu.frame_counters(), saved);
+
+    // Savestates without metadata leave them unchanged.
+    fs::remove_file(SavestateMetadata::sidecar_path(&path)).unwrap();
+    emu.run_frames(5);
+    let current = emu.frame_counters();
+    emu.savestate_mut().load_file(file_name).unwrap();
+    assert_eq!(emu.frame_counters(), current);
+}
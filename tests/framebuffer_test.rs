@@ -0,0 +1,26 @@
+use rs_desmume::{DeSmuMEError, Frame};
+
+#[test]
+fn test_frame_conversions() {
+    // 2x1 screens: red and green on top, blue and white on the bottom.
+    let frame = Frame::new(vec![0x001F, 0x03E0, 0x7C00, 0x7FFF, 0x1234], 2, 1).unwrap();
+    assert_eq!((frame.width(), frame.height()), (2, 2));
+    assert_eq!(frame.pixels().len(), 4);
+    assert_eq!(frame.top().pixel(1, 0), Some(0x03E0));
+    assert_eq!(frame.bottom().rgb(0, 0), Some([0, 0, 0xFF]));
+    assert_eq!(frame.bottom().pixel(0, 1), None);
+    assert_eq!(frame.both().pixel(1, 1), Some(0x7FFF));
+
+    assert_eq!(frame.top().to_rgba8(), vec![0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF]);
+    assert_eq!(frame.bottom().to_rgb888(), vec![0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(frame.both().to_rgb565(), vec![0xF800, 0x07E0, 0x001F, 0xFFFF]);
+
+    let mut small = [0; 7];
+    assert!(matches!(
+        frame.top().to_rgba8_into(&mut small),
+        Err(DeSmuMEError::BufferTooSmall { required: 8, actual: 7 })
+    ));
+    assert!(frame.top().to_rgb565_into(&mut [0; 1]).is_err());
+    assert!(matches!(Frame::new(vec![0; 3], 2, 1), Err(DeSmuMEError::BufferTooSmall { .. })));
+}
+
@@ -0,0 +1,51 @@
+#![cfg(feature = "async")]
+
+use futures::executor::block_on;
+use futures::{join, StreamExt};
+use rs_desmume::{DeSmuMEError, EmulatorEvent, EmulatorHandle, RgbaFrame, SpeedMode, SCREEN_HEIGHT_BOTH, SCREEN_WIDTH};
+
+#[test]
+fn test_handle_async() {
+    let handle = EmulatorHandle::spawn().unwrap();
+    let events = handle.subscribe_events().unwrap();
+    block_on(async {
+        assert!(matches!(handle.read_memory_async(0x02000000, 4).await, Err(DeSmuMEError::NoRomLoaded)));
+        assert!(matches!(handle.load_state_async(vec![]).await, Err(DeSmuMEError::NoRomLoaded)));
+
+        handle.open("tests/touchtest.nds", true).unwrap();
+        handle.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+        assert_eq!(events.recv().unwrap(), EmulatorEvent::RomOpened);
+
+        // Frames are streamed as RGBA with increasing frame numbers.
+        let frames: Vec<RgbaFrame> = handle.frame_stream().unwrap().take(3).collect().await;
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!(frame.rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4);
+            assert!(frame.rgba.chunks_exact(4).all(|pixel| pixel[3] == 0xFF));
+        }
+        assert!(frames[0].frame > 0);
+        assert!(frames.windows(2).all(|pair| pair[0].frame < pair[1].frame));
+
+        // A memory change is noticed at the end of the next frame. The change is requested
+        // after the initial value was read.
+        let addr = 0x02300000;
+        handle.write_memory_async(addr, vec![0; 4]).await.unwrap();
+        let (changed, written) = join!(
+            handle.memory_changed(addr, 4),
+            handle.write_memory_async(addr, vec![1, 2, 3, 4])
+        );
+        written.unwrap();
+        assert_eq!(changed.unwrap(), [1, 2, 3, 4]);
+        assert!(matches!(handle.memory_changed(0xFFFFFFFF, 2).await, Err(DeSmuMEError::InvalidAddress(0xFFFFFFFF))));
+
+        let state = handle.save_state_async().await.unwrap();
+        handle.load_state_async(state).await.unwrap();
+        assert_eq!(events.recv().unwrap(), EmulatorEvent::SavestateLoaded);
+    });
+
+    // The stream ends when the emulator thread stops.
+    let mut stream = handle.frame_stream().unwrap();
+    handle.shutdown().unwrap();
+    block_on(async { while stream.next().await.is_some() {} });
+    assert!(matches!(block_on(handle.save_state_async()), Err(DeSmuMEError::EmulatorThreadStopped)));
+}
@@ -0,0 +1,43 @@
+use std::thread;
+use std::time::Duration;
+use rs_desmume::{DeSmuMEError, EmulatorEvent, EmulatorHandle};
+use rs_desmume::handle::MAX_MEMORY_REQUEST;
+
+#[test]
+fn test_handle_across_threads() {
+    let handle = EmulatorHandle::spawn().unwrap();
+    let events = handle.subscribe_events().unwrap();
+
+    let other = handle.clone();
+    let ticks = thread::spawn(move || other.execute_any(|emu| emu.get_ticks()).unwrap());
+    ticks.join().unwrap();
+    assert!(matches!(handle.save_state(), Err(DeSmuMEError::NoRomLoaded)));
+
+    handle.open("tests/touchtest.nds", false).unwrap();
+    assert_eq!(events.recv().unwrap(), EmulatorEvent::RomOpened);
+    assert!(matches!(handle.read_memory(0xFFFFFFF0, 0x20), Err(DeSmuMEError::InvalidAddress(0xFFFFFFF0))));
+    assert!(matches!(handle.write_memory(0xFFFFFFFF, vec![0, 0]), Err(DeSmuMEError::InvalidAddress(0xFFFFFFFF))));
+    assert!(matches!(
+        handle.read_memory(0x02000000, MAX_MEMORY_REQUEST + 1),
+        Err(DeSmuMEError::MemoryRequestTooLarge { .. })
+    ));
+    assert_eq!(handle.read_memory(0x02000000, 4).unwrap().len(), 4);
+
+    // Frame receivers only keep the latest frame.
+    let frames = handle.subscribe_frames().unwrap();
+    handle.resume(false).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    handle.pause().unwrap();
+    assert_eq!(events.recv().unwrap(), EmulatorEvent::Resumed);
+    assert_eq!(events.recv().unwrap(), EmulatorEvent::Paused);
+    let latest = frames.recv().unwrap();
+    assert_eq!(latest.frame, handle.execute(|emu| emu.frame_count()).unwrap());
+    assert!(latest.frame > 1);
+    assert!(frames.try_recv().unwrap().is_none());
+    assert!(frames.recv_timeout(Duration::from_millis(20)).unwrap().is_none());
+
+    handle.shutdown().unwrap();
+    assert_eq!(events.recv().unwrap(), EmulatorEvent::Shutdown);
+    assert!(matches!(handle.pause(), Err(DeSmuMEError::EmulatorThreadStopped)));
+    assert!(matches!(frames.recv(), Err(DeSmuMEError::EmulatorThreadStopped)));
+}
@@ -0,0 +1,33 @@
+use rs_desmume::{DeSmuME, SpeedMode};
+
+#[test]
+fn test_headless() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+    emu.volume_set(80);
+
+    emu.cycle();
+    assert!(emu.is_frame_rendered());
+
+    // Headless frames are not rendered and the emulator is muted.
+    emu.set_headless(true);
+    assert!(emu.is_headless());
+    assert_eq!(emu.volume_get(), 0);
+    for _ in 0..10 {
+        emu.cycle();
+        assert!(!emu.is_frame_rendered());
+    }
+
+    // Single frames can still be rendered.
+    emu.render_next_frame();
+    emu.cycle();
+    assert!(emu.is_frame_rendered());
+    emu.cycle();
+    assert!(!emu.is_frame_rendered());
+
+    emu.set_headless(false);
+    assert!(!emu.is_headless());
+    assert_eq!(emu.volume_get(), 80);
+    emu.cycle();
+    assert!(emu.is_frame_rendered());
+}
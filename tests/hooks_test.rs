@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rs_desmume::{DeSmuME, HookHandle};
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+fn logger(log: &Log, name: &'static str) -> impl FnMut(&mut rs_desmume::LoadedDeSmuME) + 'static {
+    let log = log.clone();
+    move |_| log.borrow_mut().push(name)
+}
+
+#[test]
+fn test_hooks() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    let log: Log = Rc::default();
+
+    // Hooks run in the order they were registered, frame start hooks before frame end hooks.
+    let end = emu.on_frame_end(logger(&log, "end"));
+    let first = emu.on_frame_start(logger(&log, "first"));
+    let second = emu.on_frame_start(logger(&log, "second"));
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["first", "second", "end"]);
+    log.borrow_mut().clear();
+
+    // Dropping a handle removes the hook.
+    drop(first);
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["second", "end"]);
+    log.borrow_mut().clear();
+    drop(second);
+    drop(end);
+
+    // A hook can remove a hook that did not run yet, it is skipped in the same frame.
+    let victim: Rc<RefCell<Option<HookHandle>>> = Rc::default();
+    let to_remove = victim.clone();
+    let killer_log = log.clone();
+    let killer = emu.on_frame_start(move |_| {
+        killer_log.borrow_mut().push("killer");
+        to_remove.borrow_mut().take();
+    });
+    *victim.borrow_mut() = Some(emu.on_frame_start(logger(&log, "victim")));
+    emu.cycle();
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["killer", "killer"]);
+    log.borrow_mut().clear();
+    drop(killer);
+
+    // A hook can remove a hook and then emulate a frame, which runs the hooks again.
+    // The removed hook stays removed.
+    let start = emu.on_frame_start(logger(&log, "start"));
+    let victim: Rc<RefCell<Option<HookHandle>>> = Rc::default();
+    let to_remove = victim.clone();
+    let cycling_log = log.clone();
+    let cycling = emu.on_frame_end(move |emu| {
+        if to_remove.borrow_mut().take().is_some() {
+            cycling_log.borrow_mut().push("cycling");
+            emu.cycle();
+        }
+    });
+    *victim.borrow_mut() = Some(emu.on_frame_end(logger(&log, "victim")));
+    emu.cycle();
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["start", "cycling", "start", "start"]);
+    log.borrow_mut().clear();
+    drop(start);
+    drop(cycling);
+
+    // A hook can remove itself and register new hooks, which run from the next frame on.
+    let own: Rc<RefCell<Option<HookHandle>>> = Rc::default();
+    let own_handle = own.clone();
+    let added: Rc<RefCell<Option<HookHandle>>> = Rc::default();
+    let added_handle = added.clone();
+    let once_log = log.clone();
+    *own.borrow_mut() = Some(emu.on_frame_end(move |emu| {
+        once_log.borrow_mut().push("once");
+        *added_handle.borrow_mut() = Some(emu.on_frame_end(logger(&once_log, "added")));
+        own_handle.borrow_mut().take();
+    }));
+    emu.cycle();
+    assert!(own.borrow().is_none());
+    emu.cycle();
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["once", "added", "added"]);
+
+    // Forgotten hooks stay registered.
+    added.borrow_mut().take();
+    log.borrow_mut().clear();
+    emu.on_frame_end(logger(&log, "forgotten")).forget();
+    emu.cycle();
+    assert_eq!(*log.borrow(), ["forgotten"]);
+}
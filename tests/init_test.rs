@@ -0,0 +1,32 @@
+use std::sync::{Arc, Barrier};
+use std::thread;
+use rs_desmume::{free_desmume, DeSmuME, DeSmuMEError};
+
+#[test]
+fn test_init_guard() {
+    // Every thread keeps its instance until all threads tried to create one.
+    let barrier = Arc::new(Barrier::new(8));
+    let threads: Vec<_> = (0..8).map(|_| {
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let emu = DeSmuME::init();
+            barrier.wait();
+            match emu {
+                Ok(_) => true,
+                Err(DeSmuMEError::AlreadyInit) => false,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        })
+    }).collect();
+    let created = threads.into_iter().map(|t| t.join().unwrap()).filter(|&ok| ok).count();
+    assert_eq!(created, 1);
+
+    let emu = DeSmuME::init().unwrap();
+    assert!(matches!(DeSmuME::init(), Err(DeSmuMEError::AlreadyInit)));
+    assert!(matches!(free_desmume(), Err(DeSmuMEError::AlreadyInit)));
+    drop(emu);
+
+    free_desmume().unwrap();
+    free_desmume().unwrap();
+    assert!(matches!(DeSmuME::init(), Err(DeSmuMEError::Freed)));
+}
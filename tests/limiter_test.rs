@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+use rs_desmume::limiter::MIN_SPEED;
+use rs_desmume::{DeSmuME, DeSmuMEError, SpeedMode};
+
+#[test]
+fn test_speed_modes() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    assert_eq!(emu.speed_mode(), SpeedMode::Unthrottled);
+    assert!(matches!(emu.set_speed_mode(SpeedMode::Speed(0.1)), Err(DeSmuMEError::InvalidSpeed(_))));
+    assert!(matches!(emu.set_speed_mode(SpeedMode::Speed(f32::NAN)), Err(DeSmuMEError::InvalidSpeed(_))));
+    assert_eq!(emu.fps(), 0.0);
+
+    // 30 frames at 4x speed take at least 29 quarter frames.
+    emu.set_speed_mode(SpeedMode::Speed(4.0)).unwrap();
+    let start = Instant::now();
+    emu.run_frames(30);
+    assert!(start.elapsed() >= Duration::from_secs_f64(29.0 / (59.8261 * 4.0)));
+    assert!(emu.fps() > 0.0 && emu.speed_percent() <= 401.0);
+
+    // Changing the mode restarts the measurement. Unthrottled frames are faster than frames at
+    // the lowest speed (about 15 frames per second).
+    emu.set_speed_mode(SpeedMode::Speed(MIN_SPEED)).unwrap();
+    assert_eq!(emu.fps(), 0.0);
+    emu.run_frames(4);
+    let throttled = emu.fps();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+    assert_eq!(emu.speed_mode(), SpeedMode::Unthrottled);
+    emu.run_frames(4);
+    assert!(emu.fps() > throttled);
+}
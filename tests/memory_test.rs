@@ -0,0 +1,23 @@
+use rs_desmume::DeSmuME;
+use rs_desmume::mem::{IndexMove, IndexSet};
+
+const MAIN_RAM: u32 = 0x02000000;
+
+#[test]
+fn test_memory_ranges() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", false).unwrap();
+
+    // Ranges include their last element, for every access width.
+    let bytes: Vec<u8> = (1..=16).collect();
+    emu.memory_mut().u8_mut().index_set(MAIN_RAM..MAIN_RAM + 16, &bytes);
+    assert_eq!(emu.memory().u8().index_move(MAIN_RAM..MAIN_RAM + 16), bytes);
+    assert_eq!(emu.memory().u8().index_move(MAIN_RAM..=MAIN_RAM + 15), bytes);
+    assert_eq!(emu.memory().u8().index_move(MAIN_RAM + 15..MAIN_RAM + 16), vec![16]);
+    assert_eq!(emu.memory().u16().index_move(MAIN_RAM..MAIN_RAM + 16).len(), 8);
+    assert_eq!(emu.memory().u32().index_move(MAIN_RAM..MAIN_RAM + 16), vec![0x04030201, 0x08070605, 0x0C0B0A09, 0x100F0E0D]);
+
+    emu.memory_mut().u16_mut().index_set(MAIN_RAM..=MAIN_RAM + 3, &vec![0xAAAA, 0xBBBB]);
+    assert_eq!(emu.memory().u16().index_move(MAIN_RAM..MAIN_RAM + 4), vec![0xAAAA, 0xBBBB]);
+    emu.memory_mut().u32_mut().index_set(MAIN_RAM + 12..MAIN_RAM + 16, &vec![0xDEADBEEF]);
+    assert_eq!(emu.memory().u8().index_move(MAIN_RAM + 12..MAIN_RAM + 16), vec![0xEF, 0xBE, 0xAD, 0xDE]);
+}
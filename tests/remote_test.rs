@@ -0,0 +1,55 @@
+use std::env;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use rs_desmume::{DeSmuMEError, RemoteDeSmuME};
+
+#[test]
+fn desmume_worker() {
+    rs_desmume::remote::worker_main();
+}
+
+fn spawn() -> RemoteDeSmuME {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command.args(["desmume_worker", "--exact", "--quiet"]);
+    RemoteDeSmuME::spawn_command(command).unwrap()
+}
+
+#[test]
+fn test_remote_instances() {
+    let mut first = spawn();
+    let mut second = spawn();
+    assert_ne!(first.id(), second.id());
+
+    first.keypad_update(0x3).unwrap();
+    assert!(!second.is_running().unwrap());
+    // Errors of the worker keep their variant.
+    assert!(matches!(second.read_memory(0x02000000, 16), Err(DeSmuMEError::NoRomLoaded)));
+    assert!(matches!(
+        second.open(Path::new("does/not/exist.nds"), false),
+        Err(DeSmuMEError::Io(e)) if e.kind() == ErrorKind::NotFound
+    ));
+}
+
+#[test]
+fn test_remote_invalid_memory_range() {
+    let mut remote = spawn();
+    remote.open(Path::new("tests/touchtest.nds"), false).unwrap();
+
+    assert!(matches!(remote.read_memory(0xFFFFFFF0, 0x20), Err(DeSmuMEError::InvalidAddress(0xFFFFFFF0))));
+    assert!(matches!(remote.write_memory(0xFFFFFFFF, &[0, 0]), Err(DeSmuMEError::InvalidAddress(0xFFFFFFFF))));
+    assert!(matches!(
+        remote.read_memory(0x02000000, u32::MAX),
+        Err(DeSmuMEError::MemoryRequestTooLarge { len, .. }) if len == u32::MAX as usize
+    ));
+    // The worker is still alive after rejecting the requests.
+    assert_eq!(remote.read_memory(0x02000000, 4).unwrap().len(), 4);
+}
+
+#[test]
+fn test_remote_without_worker_main() {
+    // The test binary runs no test calling worker_main and exits.
+    let mut command = Command::new(env::current_exe().unwrap());
+    command.args(["no_such_test", "--exact"]);
+    assert!(matches!(RemoteDeSmuME::spawn_command(command), Err(DeSmuMEError::Remote(_))));
+}
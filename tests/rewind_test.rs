@@ -0,0 +1,29 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use rs_desmume::{DeSmuME, RewindConfig, SpeedMode};
+
+#[test]
+fn test_rewind_capacity_and_replay() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+    emu.enable_rewind(RewindConfig { interval: 2, capacity: 3 });
+
+    let frame_ends = Rc::new(Cell::new(0));
+    let counter = frame_ends.clone();
+    let _hook = emu.on_frame_end(move |_| counter.set(counter.get() + 1));
+
+    emu.run_frames(10);
+    assert_eq!(frame_ends.get(), 10);
+    // Snapshots were taken at frames 0, 2, 4, 6 and 8, only the newest three are kept.
+    assert_eq!(emu.rewind_available(), 6);
+
+    // The snapshot of frame 4 is restored and one frame is replayed, running the hooks.
+    assert_eq!(emu.rewind(5).unwrap(), 5);
+    assert_eq!(frame_ends.get(), 11);
+    assert_eq!(emu.frame_count(), 5);
+    assert_eq!(emu.rewind_available(), 1);
+
+    // Rewinding further than possible stops at the oldest snapshot.
+    assert_eq!(emu.rewind(100).unwrap(), 1);
+    assert_eq!(emu.rewind_available(), 0);
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use rs_desmume::{DeSmuME, DeSmuMEError};
+use rs_desmume::rom::RomHeader;
+
+fn touchtest() -> Vec<u8> {
+    fs::read("tests/touchtest.nds").unwrap()
+}
+
+#[test]
+fn test_rom_header_valid() {
+    let rom = touchtest();
+    let header = RomHeader::parse(&rom).unwrap();
+    assert_eq!("####", header.game_code());
+    header.verify_checksum().unwrap();
+    header.validate_size(rom.len()).unwrap();
+}
+
+#[test]
+fn test_rom_header_errors() {
+    let rom = touchtest();
+    assert!(matches!(RomHeader::parse(&rom[..0x100]), Err(DeSmuMEError::RomTooSmall(0x100))));
+
+    let header = RomHeader::parse(&rom).unwrap();
+    assert!(matches!(header.validate_size(0x1000), Err(DeSmuMEError::RomTruncated { actual: 0x1000, .. })));
+
+    // Binaries ending beyond the address space can not be contained in any image.
+    let mut huge = rom.clone();
+    huge[0x20..0x24].fill(0xFF);
+    huge[0x2C..0x30].fill(0xFF);
+    let header = RomHeader::parse(&huge).unwrap();
+    assert!(header.required_size() > u32::MAX as usize);
+    assert!(matches!(header.validate_size(rom.len()), Err(DeSmuMEError::RomTruncated { .. })));
+}
+
+#[test]
+fn test_rom_bad_header_checksum() {
+    let mut rom = touchtest();
+    rom[0x00] ^= 0xFF;
+    // The checksum is only checked on request, DeSmuME boots such ROMs.
+    let header = RomHeader::parse(&rom).unwrap();
+    assert!(matches!(header.verify_checksum(), Err(DeSmuMEError::RomBadHeaderChecksum { .. })));
+    let emu = DeSmuME::init().unwrap().open_bytes(&rom, false).unwrap();
+    assert!(!emu.is_running());
+}
@@ -1,7 +1,5 @@
 use std::env::current_dir;
-use std::thread::sleep;
-use std::time::Duration;
-use rs_desmume::DeSmuME;
+use rs_desmume::{DeSmuME, SpeedMode};
 use rs_desmume::mem::{IndexMove, IndexSet, Processor, Register};
 
 extern "C" fn print_addr(addr: u32, size: i32) -> i32 {
@@ -11,16 +9,17 @@ extern "C" fn print_addr(addr: u32, size: i32) -> i32 {
 
 #[test]
 fn test_running() {
-    let mut emu = DeSmuME::init().unwrap();
+    let rom_path = current_dir().unwrap()
+        .join("tests/touchtest.nds");
 
-    loop {
-        let rom_path = current_dir().unwrap()
-            .join("tests/touchtest.nds");
+    let rom_path_str = rom_path
+        .to_str()
+        .unwrap();
 
-        let rom_path_str = rom_path
-            .to_str()
-            .unwrap();
+    let mut emu = DeSmuME::init().unwrap().open(rom_path_str, false).unwrap();
+    emu.set_speed_mode(SpeedMode::RealTime).unwrap();
 
+    loop {
         emu.open(rom_path_str, false).unwrap();
         emu.resume(false);
 
@@ -48,7 +47,6 @@ fn test_running() {
             println!("after: 0x4000000..+16 as i32: {:?}", emu.memory().i32().index_move(0x4000000..0x4000010));
 
             window = emu.create_sdl_window(true, true).unwrap();
-            sleep(Duration::new(0, 16666667))
         }
         //println!("Accessing entire mem... stand by.");
         //println!("entire mem: {:?}", emu.memory().u8().index_move(..));
@@ -0,0 +1,29 @@
+use rs_desmume::{DeSmuME, DeSmuMEError, SpeedMode};
+use rs_desmume::mem::IndexSet;
+
+#[test]
+fn test_run_until() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.set_speed_mode(SpeedMode::Unthrottled).unwrap();
+
+    // A predicate that is already true does not emulate any frame.
+    let mut checks = 0;
+    assert_eq!(emu.run_until(|_| { checks += 1; true }, 100).unwrap(), 0);
+    assert_eq!(checks, 1);
+    assert_eq!(emu.frame_count(), 0);
+    assert_eq!(emu.run_until(|_| true, 0).unwrap(), 0);
+
+    // The predicate is checked after every frame.
+    assert_eq!(emu.run_until(|emu| emu.frame_count() == 5, 100).unwrap(), 5);
+    assert_eq!(emu.frame_count(), 5);
+
+    // Running out of frames is an error, after exactly `max_frames` frames.
+    assert!(matches!(emu.run_until(|_| false, 10), Err(DeSmuMEError::Timeout(10))));
+    assert_eq!(emu.frame_count(), 15);
+    assert!(matches!(emu.run_until(|_| false, 0), Err(DeSmuMEError::Timeout(0))));
+    assert_eq!(emu.frame_count(), 15);
+
+    emu.memory_mut().u16_mut().index_set(0x02000000, &0x1234);
+    assert_eq!(emu.run_until_memory(0x02000000, 0x1234u16, 10).unwrap(), 0);
+    assert!(matches!(emu.run_until_memory(0x02000000, 0x4321u16, 3), Err(DeSmuMEError::Timeout(3))));
+}
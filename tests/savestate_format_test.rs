@@ -0,0 +1,129 @@
+use std::io::Write;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use rs_desmume::DeSmuMEError;
+use rs_desmume::mem::{Processor, Register};
+use rs_desmume::savestate::format::{ChunkData, ChunkKind, SavestateFile};
+
+fn field(name: &[u8; 4], size: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = name.to_vec();
+    out.extend(size.to_le_bytes());
+    out.extend((data.len() as u32 / size).to_le_bytes());
+    out.extend(data);
+    out
+}
+
+fn chunk(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = id.to_le_bytes().to_vec();
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend(data);
+    out
+}
+
+fn savestate(compress: bool) -> Vec<u8> {
+    savestate_with_mmu(compress, &[8, 0, 0, 0, 0xAA])
+}
+
+/// The extra MMU chunk, with the backup memory and some trailing data.
+fn mmu_with_backup(backup: &[u8]) -> Vec<u8> {
+    let mut mmu = vec![0; 28];
+    mmu[0] = 8;
+    mmu.extend((backup.len() as u32).to_le_bytes());
+    mmu.extend(backup);
+    mmu.extend([0xCC; 4]);
+    mmu
+}
+
+fn savestate_with_mmu(compress: bool, mmu: &[u8]) -> Vec<u8> {
+    let regs: Vec<u8> = (0..16u32).flat_map(|r| (r * 0x100).to_le_bytes()).collect();
+    let mut arm9 = field(b"9REG", 4, &regs);
+    arm9.extend(field(b"9CPS", 4, &0x1Fu32.to_le_bytes()));
+    let mut body = chunk(1, &arm9);
+    body.extend(chunk(4, &field(b"WRAM", 1, &[1, 2, 3, 4])));
+    body.extend(chunk(61, mmu));
+    body.extend(chunk(0xFFFFFFFF, &[]));
+
+    let mut out = b"DeSmuME SState\0\0".to_vec();
+    out.extend(12u32.to_le_bytes());
+    out.extend(0x91100u32.to_le_bytes());
+    out.extend((body.len() as u32).to_le_bytes());
+    if compress {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        out.extend((compressed.len() as u32).to_le_bytes());
+        out.extend(compressed);
+    } else {
+        out.extend(0xFFFFFFFFu32.to_le_bytes());
+        out.extend(body);
+    }
+    out
+}
+
+#[test]
+fn test_parse_savestate() {
+    for compress in [false, true] {
+        let state = SavestateFile::parse(&savestate(compress)).unwrap();
+        assert_eq!(12, state.version);
+        assert_eq!(3, state.chunks.len());
+        assert_eq!(Some(&[1u8, 2, 3, 4][..]), state.main_ram());
+        assert_eq!(Some(0x300), state.register(Processor::Arm9, Register::R3));
+        assert_eq!(Some(0xF00), state.register(Processor::Arm9, Register::PC));
+        assert_eq!(Some(0x1F), state.register(Processor::Arm9, Register::CPSR));
+        assert_eq!(None, state.register(Processor::Arm7, Register::R0));
+        let mmu = state.chunk(ChunkKind::MmuExtra).unwrap();
+        assert_eq!(Some(8), mmu.version());
+        assert_eq!(5, mmu.size());
+    }
+}
+
+#[test]
+fn test_parse_invalid_savestate() {
+    assert!(SavestateFile::parse(b"not a savestate").is_err());
+    let mut truncated = savestate(false);
+    truncated.truncate(40);
+    assert!(SavestateFile::parse(&truncated).is_err());
+}
+
+#[test]
+fn test_patch_savestate() {
+    let mut state = SavestateFile::parse(&savestate(true)).unwrap();
+    state.write_memory(0x02000001, &[0xFF, 0xFE]).unwrap();
+    assert!(state.write_memory(0x02000003, &[0, 0]).is_err());
+    state.set_register(Processor::Arm9, Register::LR, 0x02001234).unwrap();
+    state.field_mut(ChunkKind::Memory, "WRAM").unwrap().set_data(vec![9; 16]).unwrap();
+
+    let patched = SavestateFile::parse(&state.to_bytes()).unwrap();
+    assert_eq!(Some(&[9u8; 16][..]), patched.main_ram());
+    assert_eq!(16, patched.field(ChunkKind::Memory, "WRAM").unwrap().count);
+    assert_eq!(Some(0x02001234), patched.register(Processor::Arm9, Register::R14));
+    assert_eq!(state.chunks.len(), patched.chunks.len());
+}
+
+#[test]
+fn test_backup_data() {
+    let mut state = SavestateFile::parse(&savestate_with_mmu(false, &mmu_with_backup(&[1; 512]))).unwrap();
+    assert_eq!(Some(&[1u8; 512][..]), state.backup_data());
+
+    // Smaller backups are padded to the size of the backup memory, larger ones are rejected.
+    state.set_backup_data(&[2; 100]).unwrap();
+    let patched = SavestateFile::parse(&state.to_bytes()).unwrap();
+    let backup = patched.backup_data().unwrap();
+    assert_eq!(512, backup.len());
+    assert_eq!((&[2u8; 100][..], &[0xFFu8; 412][..]), backup.split_at(100));
+    assert!(matches!(state.set_backup_data(&[3; 513]), Err(DeSmuMEError::InvalidBackup(_))));
+    assert_eq!(backup, state.backup_data().unwrap());
+    // The data after the backup memory is kept.
+    assert_eq!(28 + 4 + 512 + 4, state.chunk(ChunkKind::MmuExtra).unwrap().size());
+    match &state.chunk(ChunkKind::MmuExtra).unwrap().data {
+        ChunkData::Raw(data) => assert!(data.ends_with(&[0xCC; 4])),
+        ChunkData::Fields(_) => unreachable!(),
+    }
+
+    // While the save type is not detected yet, only the sizes of known save types are accepted.
+    let mut state = SavestateFile::parse(&savestate_with_mmu(false, &mmu_with_backup(&[]))).unwrap();
+    assert_eq!(Some(&[][..]), state.backup_data());
+    assert!(matches!(state.set_backup_data(&[0; 1000]), Err(DeSmuMEError::InvalidBackup(_))));
+    state.set_backup_data(&[4; 8192]).unwrap();
+    assert_eq!(Some(&[4u8; 8192][..]), state.backup_data());
+}
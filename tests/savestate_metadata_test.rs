@@ -0,0 +1,56 @@
+use std::fs;
+use rs_desmume::{DeSmuMEError, SavestateMetadata, SCREEN_HEIGHT_BOTH, SCREEN_WIDTH};
+
+fn metadata() -> SavestateMetadata {
+    SavestateMetadata {
+        title: "TOUCHTEST".to_owned(),
+        game_code: "####".to_owned(),
+        frame: 1234,
+        lag_frames: 56,
+        label: "Before the boss".to_owned(),
+        screens: Some((0..SCREEN_WIDTH * SCREEN_HEIGHT_BOTH * 4).map(|i| i as u8).collect()),
+    }
+}
+
+fn assert_same(a: &SavestateMetadata, b: &SavestateMetadata) {
+    assert_eq!(
+        (&a.title, &a.game_code, a.frame, a.lag_frames, &a.label, &a.screens),
+        (&b.title, &b.game_code, b.frame, b.lag_frames, &b.label, &b.screens)
+    );
+}
+
+#[test]
+fn test_metadata_roundtrip() {
+    let metadata = metadata();
+    assert_same(&metadata, &SavestateMetadata::parse(&metadata.to_bytes()).unwrap());
+    let without_screens = SavestateMetadata { screens: None, ..metadata };
+    assert_same(&without_screens, &SavestateMetadata::parse(&without_screens.to_bytes()).unwrap());
+}
+
+#[test]
+fn test_metadata_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = dir.path().join("game.dst");
+    assert_eq!(SavestateMetadata::sidecar_path(&state), dir.path().join("game.dst.meta"));
+    assert!(SavestateMetadata::read_for(&state).unwrap().is_none());
+
+    metadata().write_for(&state).unwrap();
+    assert_same(&metadata(), &SavestateMetadata::read_for(&state).unwrap().unwrap());
+
+    fs::write(SavestateMetadata::sidecar_path(&state), b"garbage").unwrap();
+    assert!(matches!(SavestateMetadata::read_for(&state), Err(DeSmuMEError::InvalidSavestateMetadata(_))));
+}
+
+#[test]
+fn test_metadata_invalid() {
+    let bytes = metadata().to_bytes();
+    for len in [0, 8, 20, 28, bytes.len() - 1] {
+        assert!(matches!(SavestateMetadata::parse(&bytes[..len]), Err(DeSmuMEError::InvalidSavestateMetadata(_))));
+    }
+    let mut version = bytes.clone();
+    version[8] = 2;
+    assert!(SavestateMetadata::parse(&version).is_err());
+
+    let small_screens = SavestateMetadata { screens: Some(vec![0; 16]), ..metadata() };
+    assert!(SavestateMetadata::parse(&small_screens.to_bytes()).is_err());
+}
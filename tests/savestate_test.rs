@@ -0,0 +1,61 @@
+use rs_desmume::{DeSmuME, DeSmuMEError, SimpleDate, NB_STATES};
+
+#[test]
+fn test_savestates() {
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", true).unwrap();
+    emu.input_mut().keypad_update(0x1);
+    emu.cycle();
+
+    let (state, counters) = emu.savestate_mut().save_to_vec_with_counters().unwrap();
+    assert!(!state.is_empty());
+    emu.input_mut().keypad_update(0x2);
+    emu.cycle();
+    assert_eq!(emu.frame_count(), 2);
+    emu.savestate_mut().load_from_slice_with_counters(&state, counters).unwrap();
+    assert_eq!(emu.frame_count(), 1);
+
+    // Garbage and truncated buffers are rejected.
+    assert!(matches!(emu.savestate_mut().load_from_slice(&[]), Err(DeSmuMEError::LoadSavestateFailed(_))));
+    assert!(matches!(
+        emu.savestate_mut().load_from_slice(b"not a savestate"),
+        Err(DeSmuMEError::LoadSavestateFailed(_))
+    ));
+    assert!(matches!(emu.savestate_mut().load_from_slice(&state[..8]), Err(DeSmuMEError::LoadSavestateFailed(_))));
+    // The emulator still works after rejecting them.
+    emu.savestate_mut().load_from_slice(&state).unwrap();
+
+    // Saving a savestate file without metadata replaces the metadata of the previous savestate.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.dst");
+    let file_name = path.to_str().unwrap();
+    let metadata = emu.savestate_metadata("label", false);
+    emu.savestate_mut().save_file_with_metadata(file_name, &metadata).unwrap();
+    assert_eq!(emu.savestate().file_metadata(file_name).unwrap().unwrap().label, "label");
+    emu.savestate_mut().save_file(file_name).unwrap();
+    let metadata = emu.savestate().file_metadata(file_name).unwrap().unwrap();
+    assert_eq!((metadata.label.as_str(), metadata.frame, metadata.screens), ("", 1, None));
+
+    // Slots are checked against the number of slots.
+    let last = NB_STATES as u8 - 1;
+    assert!(emu.savestate().exists(last).is_ok());
+    assert!(matches!(emu.savestate().exists(last + 1), Err(DeSmuMEError::InvalidSavestateSlot(_))));
+    assert!(matches!(emu.savestate().slot_info(last + 1), Err(DeSmuMEError::InvalidSavestateSlot(_))));
+    assert!(matches!(emu.savestate_mut().save(last + 1), Err(DeSmuMEError::InvalidSavestateSlot(_))));
+    assert!(matches!(emu.savestate_mut().load(u8::MAX), Err(DeSmuMEError::InvalidSavestateSlot(_))));
+}
+
+#[test]
+fn test_slot_date() {
+    let date: SimpleDate = "07-Mar-2023 14:05:09".parse().unwrap();
+    assert_eq!(date, SimpleDate { year: 2023, month: 3, day: 7, hour: 14, minute: 5, second: 9, millisecond: 0 });
+    // Surrounding whitespace and the case of the month are ignored.
+    let date: SimpleDate = " 31-dec-1999 23:59:60\n".parse().unwrap();
+    assert_eq!((date.year, date.month, date.day, date.second), (1999, 12, 31, 60));
+
+    for invalid in [
+        "", "07-Mar-2023", "07-Mae-2023 14:05:09", "07-Mar-2023 14:05", "x-Mar-2023 14:05:09",
+        "32-Mar-2023 14:05:09", "07-Mar-2023 24:05:09"
+    ] {
+        assert!(matches!(invalid.parse::<SimpleDate>(), Err(DeSmuMEError::InvalidSavestateDate(_))), "{}", invalid);
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+use rs_desmume::screenshot::ImageFormat;
+use rs_desmume::{DeSmuMEError, Frame, ScreenSelection};
+
+fn frame() -> Frame {
+    // 2x2 screens, red/green/blue/white on top and black on the bottom.
+    Frame::new(vec![0x001F, 0x03E0, 0x7C00, 0x7FFF, 0, 0, 0, 0], 2, 2).unwrap()
+}
+
+#[test]
+fn test_ppm() {
+    let mut out = vec![];
+    frame().select(ScreenSelection::Top).write_image(&mut out, ImageFormat::Ppm).unwrap();
+    let mut expected = b"P6\n2 2\n255\n".to_vec();
+    expected.extend([0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(out, expected);
+
+    out.clear();
+    frame().select(ScreenSelection::Both).write_image(&mut out, ImageFormat::Ppm).unwrap();
+    assert!(out.starts_with(b"P6\n2 4\n255\n"));
+}
+
+#[test]
+fn test_bmp() {
+    let mut out = vec![];
+    frame().select(ScreenSelection::Top).write_image(&mut out, ImageFormat::Bmp).unwrap();
+    // Lines of 6 bytes are padded to 8 bytes.
+    assert_eq!(out.len(), 54 + 2 * 8);
+    assert_eq!(&out[..2], b"BM");
+    assert_eq!(u32::from_le_bytes(out[2..6].try_into().unwrap()), 70);
+    // The bottom line comes first, as BGR.
+    assert_eq!(&out[54..62], &[0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0]);
+    assert_eq!(&out[62..70], &[0, 0, 0xFF, 0, 0xFF, 0, 0, 0]);
+}
+
+#[test]
+fn test_format_from_path() {
+    assert_eq!(ImageFormat::from_path(Path::new("shot.PPM")).unwrap(), ImageFormat::Ppm);
+    assert_eq!(ImageFormat::from_path(Path::new("shot.bmp")).unwrap(), ImageFormat::Bmp);
+    assert!(matches!(ImageFormat::from_path(Path::new("shot.gif")), Err(DeSmuMEError::UnsupportedImageFormat(_))));
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn test_png() {
+    let mut out = vec![];
+    frame().select(ScreenSelection::Bottom).write_image(&mut out, ImageFormat::Png).unwrap();
+    assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+}
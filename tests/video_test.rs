@@ -0,0 +1,88 @@
+use std::fs;
+use rs_desmume::compositor::{Layout, RgbaImage, Rotation};
+use rs_desmume::{Compositor, DeSmuME, DeSmuMEError, LoadedDeSmuME};
+
+/// Emulate `frames` frames and return the images that should have been recorded.
+fn record(emu: &mut LoadedDeSmuME, compositor: &Compositor, frames: usize) -> Vec<RgbaImage> {
+    (0..frames).map(|_| {
+        emu.cycle();
+        compositor.compose(&emu.frame()).unwrap()
+    }).collect()
+}
+
+#[test]
+fn test_recording() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut emu = DeSmuME::init().unwrap().open("tests/touchtest.nds", false).unwrap();
+    assert!(matches!(emu.stop_recording(), Err(DeSmuMEError::NotRecording)));
+    assert!(matches!(
+        emu.start_recording(dir.path().join("video.mp4"), Compositor::default()),
+        Err(DeSmuMEError::UnsupportedVideoFormat(_))
+    ));
+    assert!(!emu.is_recording());
+
+    let y4m = dir.path().join("video.y4m");
+    let rotated = Compositor { layout: Layout::Horizontal, rotation: Rotation::Deg90, ..Default::default() };
+    emu.start_recording(&y4m, rotated).unwrap();
+    assert!(emu.is_recording());
+    let y4m_images = record(&mut emu, &rotated, 3);
+    // Starting another recording finishes the previous one.
+    let avi = dir.path().join("video.avi");
+    emu.start_recording(&avi, Compositor::default()).unwrap();
+    let avi_images = record(&mut emu, &Compositor::default(), 2);
+    assert_eq!(emu.stop_recording().unwrap(), 2);
+    assert!(!emu.is_recording());
+
+    // Y4M: the header, then every frame as "FRAME" marker and the Y, U and V planes.
+    let y4m = fs::read(&y4m).unwrap();
+    let header = b"YUV4MPEG2 W192 H512 F598261:10000 Ip A1:1 C444\n";
+    assert_eq!(&y4m[..header.len()], header);
+    let pixels = 192 * 512;
+    let frames: Vec<&[u8]> = y4m[header.len()..].chunks(6 + pixels * 3).collect();
+    assert_eq!(frames.len(), y4m_images.len());
+    for (frame, image) in frames.iter().zip(&y4m_images) {
+        assert_eq!(&frame[..6], b"FRAME\n");
+        assert_eq!(frame.len(), 6 + pixels * 3);
+        let luma: Vec<u8> = image.data().chunks_exact(4).map(|pixel| {
+            let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+            (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+        }).collect();
+        assert_eq!(&frame[6..6 + pixels], luma);
+    }
+    assert_ne!(frames[0], frames[1]);
+
+    // AVI: the headers, the 'movi' list with the frames and the 'idx1' index.
+    let avi = fs::read(&avi).unwrap();
+    let u32_at = |offset: usize| u32::from_le_bytes(avi[offset..offset + 4].try_into().unwrap());
+    let frame_size = 256 * 384 * 3;
+    assert_eq!(&avi[..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, avi.len() - 8);
+    assert_eq!(&avi[8..12], b"AVI ");
+    assert_eq!((u32_at(48), u32_at(140)), (2, 2));
+    assert_eq!((u32_at(64), u32_at(68)), (256, 384));
+    assert_eq!(&avi[220..224], b"movi");
+    assert_eq!(u32_at(216) as usize, 4 + 2 * (8 + frame_size));
+    for (i, image) in avi_images.iter().enumerate() {
+        let chunk = 224 + i * (8 + frame_size);
+        assert_eq!(&avi[chunk..chunk + 4], b"00db");
+        assert_eq!(u32_at(chunk + 4) as usize, frame_size);
+        // BGR, bottom line first.
+        let expected: Vec<u8> = image.data().chunks_exact(256 * 4).rev()
+            .flat_map(|line| line.chunks_exact(4).flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]))
+            .collect();
+        assert_eq!(&avi[chunk + 8..chunk + 8 + frame_size], expected);
+    }
+    let index = 224 + 2 * (8 + frame_size);
+    assert_eq!(&avi[index..index + 4], b"idx1");
+    assert_eq!(u32_at(index + 4), 2 * 16);
+    for i in 0..2 {
+        let entry = index + 8 + i * 16;
+        assert_eq!(&avi[entry..entry + 4], b"00db");
+        // Key frame flag, offset relative to 'movi' and size.
+        assert_eq!(
+            (u32_at(entry + 4), u32_at(entry + 8) as usize, u32_at(entry + 12) as usize),
+            (0x10, 4 + i * (8 + frame_size), frame_size)
+        );
+    }
+    assert_eq!(avi.len(), index + 8 + 2 * 16);
+}